@@ -3,30 +3,59 @@ use std::fs::File;
 use chrono::{Local, Datelike, Timelike};
 
 use crate::data::*;
+use crate::highlight::{tokenize, Token};
+use crate::mathml::tex_to_mathml;
+use crate::parser::ref_text;
+use crate::sanitize::{sanitize, is_external_url};
 
 use Block::*;
 use Span::*;
 use Elem::*;
 
-pub fn gen_html(dest: &mut File, title: &String, toc: &List, content: &Vec<Block>, template: &Vec<Elem>) -> Result<(), io::Error> {
-    let mut codegen = CodeGen::new(dest);
-    codegen.gen_html(title, toc, content, template)
+pub struct Options {
+    // highlight code blocks here instead of relying on highlight.js
+    pub highlight: bool,
+    // convert math into MathML here instead of relying on MathJax
+    pub mathml: bool,
+    // pass raw HTML through, which should be disabled for untrusted input
+    pub html: bool,
+    // sanitize untrusted documents and mark external links as untrusted
+    pub safe: bool,
+}
+
+pub fn gen_html(dest: &mut File, meta: &Metadata, toc: &List, content: &Vec<Block>, footnotes: &[Footnote], template: &Vec<Elem>, options: &Options) -> Result<(), io::Error> {
+    let mut codegen = CodeGen::new(dest, options);
+    if options.safe {
+        let (mut content, mut footnotes) = (content.clone(), footnotes.to_vec());
+        sanitize(&mut content, &mut footnotes);
+        return codegen.gen_html(meta, toc, &content, &footnotes, template);
+    }
+    codegen.gen_html(meta, toc, content, footnotes, template)
 }
 
 struct CodeGen<'a> {
     dest: &'a mut File,
+    options: &'a Options,
 }
 
 impl<'a> CodeGen<'a> {
-    fn new(dest: &'a mut File) -> Self {
-        CodeGen { dest }
+    fn new(dest: &'a mut File, options: &'a Options) -> Self {
+        CodeGen { dest, options }
     }
 
-    fn gen_html(&mut self, title: &String, toc: &List, content: &Vec<Block>, template: &Vec<Elem>) -> Result<(), io::Error> {
-        let datetime = Local::now();
+    fn gen_html(&mut self, meta: &Metadata, toc: &List, content: &Vec<Block>, footnotes: &[Footnote], template: &Vec<Elem>) -> Result<(), io::Error> {
+        // the date in the front matter is kept instead of the time of conversion
+        let datetime = meta.date.unwrap_or_else(|| Local::now().naive_local());
+        // footnotes follow the content unless the template places them
+        let placed = template.iter().any(|chunk| matches!(chunk, Footnotes(_)));
         for chunk in template {
             match chunk {
-                Title => { write!(self.dest, "{}", title)?; },
+                Title => { write!(self.dest, "{}", escape_text(&meta.title))?; },
+                Author => { write!(self.dest, "{}", escape_text(meta.author.as_deref().unwrap_or_default()))?; },
+                Description => { write!(self.dest, "{}", escape_text(meta.description.as_deref().unwrap_or_default()))?; },
+                Tags => { write!(self.dest, "{}", escape_text(&meta.tags.join(", ")))?; },
+                Draft => { write!(self.dest, "{}", meta.draft)?; },
+                Meta(key) => { write!(self.dest, "{}", escape_text(meta.extra.get(key).map_or("", |value| value.as_str())))?; },
                 Year => { write!(self.dest, "{:04}", datetime.year())?; },
                 Month => { write!(self.dest, "{:02}", datetime.month())?; },
                 Day => { write!(self.dest, "{:02}", datetime.day())?; },
@@ -34,7 +63,13 @@ impl<'a> CodeGen<'a> {
                 Minute => { write!(self.dest, "{:02}", datetime.minute())?; },
                 Second => { write!(self.dest, "{:02}", datetime.second())?; },
                 Toc(indent) => { self.gen_toc(toc, *indent)?; },
-                Content(indent) => { self.gen_content(content, *indent)?; },
+                Content(indent) => {
+                    self.gen_content(content, *indent)?;
+                    if !placed {
+                        self.gen_footnotes(footnotes, *indent)?;
+                    }
+                },
+                Footnotes(indent) => { self.gen_footnotes(footnotes, *indent)?; },
                 Str(text) => { write!(self.dest, "{}", text)?; },
             }
         }
@@ -43,40 +78,81 @@ impl<'a> CodeGen<'a> {
 
     fn gen_toc(&mut self, toc: &List, indent: usize) -> Result<(), io::Error> {
         writeln!(self.dest)?;
-        self.gen_list(&toc, indent)
+        self.gen_list(toc, indent)
     }
 
     fn gen_content(&mut self, content: &Vec<Block>, indent: usize) -> Result<(), io::Error> {
         writeln!(self.dest)?;
-        for block in content {
-            match block {
-                Header { spans, level, id } => { self.gen_header(spans, level, id, indent)?; },
-                Blockquote { lines } => { self.gen_blockquote(lines, indent)?; },
-                ListElement(list) => { self.gen_list(list, indent)?; },
-                Image { url } => { self.gen_image(url, indent)?; },
-                LinkCard { title, image, url, description, site_name } => { self.gen_link_card(title, image, url, description, site_name, indent)?; },
-                Table { head, body } => { self.gen_table(head, body, indent)?; },
-                Paragraph { spans } => { self.gen_paragraph(spans, indent)?; },
-                MathBlock { math } => { self.gen_math_block(math, indent)?; },
-                CodeBlock { lang, code } => { self.gen_code_block(lang, code, indent)?; },
+        self.gen_blocks(content, indent)
+    }
+
+    fn gen_footnotes(&mut self, footnotes: &[Footnote], indent: usize) -> Result<(), io::Error> {
+        if footnotes.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(self.dest, "{:>indent$}<section class=\"footnotes\">", " ")?;
+        writeln!(self.dest, "{:>indent$}  <ol>", " ")?;
+        for footnote in footnotes {
+            writeln!(self.dest, "{:>indent$}    <li id=\"fn-{}\">", " ", footnote.number)?;
+            let backrefs: Vec<String> = (1..=footnote.refs).map(|index| {
+                let mark = if index == 1 { String::from("↩") } else { format!("↩<sup>{}</sup>", index) };
+                format!("<a class=\"footnote-backref\" href=\"#{}\">{}</a>", footnote_ref_id(footnote.number, index), mark)
+            }).collect();
+
+            // the back-references are put at the end of the last paragraph
+            let (last, blocks) = match footnote.blocks.split_last() {
+                Some((Paragraph { spans }, blocks)) => (Some(spans), blocks),
+                _ => (None, &footnote.blocks[..]),
+            };
+            for block in blocks {
+                self.gen_block(block, indent + 6)?;
             }
+            write!(self.dest, "{:>indent$}      <p>", " ")?;
+            if let Some(spans) = last {
+                self.gen_spans(spans)?;
+                write!(self.dest, " ")?;
+            }
+            writeln!(self.dest, "{}</p>", backrefs.join(" "))?;
+            writeln!(self.dest, "{:>indent$}    </li>", " ")?;
+        }
+        writeln!(self.dest, "{:>indent$}  </ol>", " ")?;
+        writeln!(self.dest, "{:>indent$}</section>", " ")
+    }
+
+    fn gen_blocks(&mut self, blocks: &Vec<Block>, indent: usize) -> Result<(), io::Error> {
+        for block in blocks {
+            self.gen_block(block, indent)?;
         }
         Ok(())
     }
 
-    fn gen_header(&mut self, spans: &Vec<Span>, level: &u32, id: &String, indent: usize) -> Result<(), io::Error> {
-        write!(self.dest, "{:>indent$}<h{} id=\"{}\">", " ", *level, *id)?;
+    fn gen_block(&mut self, block: &Block, indent: usize) -> Result<(), io::Error> {
+        match block {
+            Header { spans, level, id } => self.gen_header(spans, level, id, indent),
+            Blockquote { blocks } => self.gen_blockquote(blocks, indent),
+            ListElement(list) => self.gen_list(list, indent),
+            Block::Image { alt, url, title, width, height } => self.gen_image(alt, url, title, width, height, indent),
+            LinkCard { title, image, url, description, site_name } => self.gen_link_card(title, image, url, description, site_name, indent),
+            Table { align, head, body } => self.gen_table(align, head, body, indent),
+            Figure { id, number, caption, block } => self.gen_figure(id, *number, caption, block, indent),
+            Block::Html { html } => self.gen_html_block(html, indent),
+            ThematicBreak => self.gen_thematic_break(indent),
+            Paragraph { spans } => self.gen_paragraph(spans, indent),
+            MathBlock { math, label, number } => self.gen_math_block(math, label, *number, indent),
+            CodeBlock { lang, title, line_numbers, highlight, code } => self.gen_code_block(lang, title, *line_numbers, highlight, code, indent),
+        }
+    }
+
+    fn gen_header(&mut self, spans: &Vec<Span>, level: &u32, id: &str, indent: usize) -> Result<(), io::Error> {
+        write!(self.dest, "{:>indent$}<h{} id=\"{}\">", " ", *level, escape_attr(id))?;
         self.gen_spans(spans)?;
         writeln!(self.dest, "</h{}>", *level)
     }
 
-    fn gen_blockquote(&mut self, lines: &Vec<Vec<Span>>, indent: usize) -> Result<(), io::Error> {
+    fn gen_blockquote(&mut self, blocks: &Vec<Block>, indent: usize) -> Result<(), io::Error> {
         writeln!(self.dest, "{:>indent$}<blockquote>", " ")?;
-        for spans in lines {
-            write!(self.dest, "{:>indent$}  <p>", " ")?;
-            self.gen_spans(spans)?;
-            writeln!(self.dest, "</p>")?;
-        }
+        self.gen_blocks(blocks, indent + 2)?;
         writeln!(self.dest, "{:>indent$}</blockquote>", " ")
     }
 
@@ -85,73 +161,178 @@ impl<'a> CodeGen<'a> {
             return Ok(());
         }
 
-        writeln!(self.dest, "{:>indent$}<{}>", " ", if list.ordered { "ol" } else { "ul" })?;
+        if list.ordered && list.start != 1 {
+            writeln!(self.dest, "{:>indent$}<ol start=\"{}\">", " ", list.start)?;
+        } else {
+            writeln!(self.dest, "{:>indent$}<{}>", " ", if list.ordered { "ol" } else { "ul" })?;
+        }
         for item in &list.items {
-            writeln!(self.dest, "{:>indent$}  <li>", " ")?;
-            
-            write!(self.dest, "{:>indent$}    ", " ")?;
-            self.gen_spans(&item.spans)?;
-            writeln!(self.dest)?;
-            self.gen_list(&item.list, indent + 4)?;
-            
+            // the checkbox of a task list item is put at the start of its first paragraph
+            let mut checkbox = item.checked.map(|checked| format!("<input type=\"checkbox\" disabled{}> ", if checked { " checked" } else { "" }));
+            if checkbox.is_some() {
+                writeln!(self.dest, "{:>indent$}  <li class=\"task-list-item\">", " ")?;
+            } else {
+                writeln!(self.dest, "{:>indent$}  <li>", " ")?;
+            }
+            for block in &item.blocks {
+                match block {
+                    // paragraphs in a tight list are not wrapped in <p>
+                    Paragraph { spans } if list.tight || checkbox.is_some() => {
+                        let (open, close) = if list.tight { ("", "") } else { ("<p>", "</p>") };
+                        write!(self.dest, "{:>indent$}    {}{}", " ", open, checkbox.take().unwrap_or_default())?;
+                        self.gen_spans(spans)?;
+                        writeln!(self.dest, "{}", close)?;
+                    },
+                    _ => { self.gen_block(block, indent + 4)?; },
+                }
+            }
             writeln!(self.dest, "{:>indent$}  </li>", " ")?;
         }
         writeln!(self.dest, "{:>indent$}</{}>", " ", if list.ordered { "ol" } else { "ul" })
     }
 
-    fn gen_image(&mut self, url: &String, indent: usize) -> Result<(), io::Error> {
-        writeln!(self.dest, "{:>indent$}<div class=\"image\"><img src=\"{}\"></div>", " ", *url)
+    fn gen_image(&mut self, alt: &str, url: &str, title: &Option<String>, width: &Option<String>, height: &Option<String>, indent: usize) -> Result<(), io::Error> {
+        write!(self.dest, "{:>indent$}<div class=\"image\">", " ")?;
+        self.gen_img(alt, url, title, width, height)?;
+        writeln!(self.dest, "</div>")
     }
 
-    fn gen_link_card(&mut self, title: &String, image: &Option<String>, url: &String, description: &Option<String>, site_name: &Option<String>, indent: usize) -> Result<(), io::Error> {
-        writeln!(self.dest, "{:>indent$}<div class=\"linkcard\"><a class=\"linkcard-link\" href=\"{}\">", "", url)?;
+    fn gen_link_card(&mut self, title: &str, image: &Option<String>, url: &str, description: &Option<String>, site_name: &Option<String>, indent: usize) -> Result<(), io::Error> {
+        writeln!(self.dest, "{:>indent$}<div class=\"linkcard\"><a class=\"linkcard-link\" href=\"{}\"{}>", "", escape_url(url), self.link_rel(url))?;
         writeln!(self.dest, "{:>indent$}  <div class=\"linkcard-text\">", "")?;
-        writeln!(self.dest, "{:>indent$}    <h3 class=\"linkcard-title\">{}</h3>", "", title)?;
+        writeln!(self.dest, "{:>indent$}    <h3 class=\"linkcard-title\">{}</h3>", "", escape_text(title))?;
         if let Some(desc) = description {
-            writeln!(self.dest, "{:>indent$}    <p class=\"linkcard-description\">{}</p>", "", desc)?;
+            writeln!(self.dest, "{:>indent$}    <p class=\"linkcard-description\">{}</p>", "", escape_text(desc))?;
         }
-        writeln!(self.dest, "{:>indent$}    <img  class=\"linkcard-favicon\" src=\"http://www.google.com/s2/favicons?domain={}\"><span  class=\"linkcard-sitename\">{}</span>", "", url, site_name.clone().unwrap_or(url.clone()))?;
+        writeln!(self.dest, "{:>indent$}    <img  class=\"linkcard-favicon\" src=\"http://www.google.com/s2/favicons?domain={}\"><span  class=\"linkcard-sitename\">{}</span>", "", escape_url(url), escape_text(site_name.as_deref().unwrap_or(url)))?;
         writeln!(self.dest, "{:>indent$}  </div>", "")?;
         if let Some(img) = image {
-            writeln!(self.dest, "{:>indent$}  <img class=\"linkcard-image\" src=\"{}\">", "", img)?;
+            writeln!(self.dest, "{:>indent$}  <img class=\"linkcard-image\" src=\"{}\">", "", escape_url(img))?;
         }
         writeln!(self.dest, "{:>indent$}</a></div>", "")
     }
 
-    fn gen_table(&mut self, head: &Vec<Vec<String>>, body: &Vec<Vec<String>>, indent: usize) -> Result<(), io::Error> {
+    fn gen_table(&mut self, align: &[Option<Align>], head: &Vec<Vec<Vec<Span>>>, body: &Vec<Vec<Vec<Span>>>, indent: usize) -> Result<(), io::Error> {
         writeln!(self.dest, "{:>indent$}<table>", " ")?;
 
         writeln!(self.dest, "{:>indent$}  <thead>", " ")?;
         for row in head {
-            writeln!(self.dest, "{:>indent$}    <tr>", " ")?;
-            for data in row {
-                writeln!(self.dest, "{:>indent$}      <td>{}</td>", " ", *data)?;
-            }
-            writeln!(self.dest, "{:>indent$}    </tr>", " ")?;
+            self.gen_table_row(row, "th", align, indent)?;
         }
         writeln!(self.dest, "{:>indent$}  </thead>", " ")?;
         
         writeln!(self.dest, "{:>indent$}  <tbody>", " ")?;
         for row in body {
-            writeln!(self.dest, "{:>indent$}    <tr>", " ")?;
-            for data in row {
-                writeln!(self.dest, "{:>indent$}      <td>{}</td>", " ", *data)?;
-            }
-            writeln!(self.dest, "{:>indent$}    </tr>", " ")?;
+            self.gen_table_row(row, "td", align, indent)?;
         }
         writeln!(self.dest, "{:>indent$}  </tbody>", " ")?;
         
         writeln!(self.dest, "{:>indent$}</table>", " ")
     }
 
-    fn gen_math_block(&mut self, math: &String, indent: usize) -> Result<(), io::Error> {
-        writeln!(self.dest, "{:>indent$}<p>\\[{}\\]</p>", " ", math)
+    fn gen_table_row(&mut self, row: &[Vec<Span>], tag: &str, align: &[Option<Align>], indent: usize) -> Result<(), io::Error> {
+        writeln!(self.dest, "{:>indent$}    <tr>", " ")?;
+        for (i, data) in row.iter().enumerate() {
+            let style = match align.get(i) {
+                Some(Some(Align::Left)) => " style=\"text-align: left\"",
+                Some(Some(Align::Center)) => " style=\"text-align: center\"",
+                Some(Some(Align::Right)) => " style=\"text-align: right\"",
+                _ => "",
+            };
+            write!(self.dest, "{:>indent$}      <{}{}>", " ", tag, style)?;
+            self.gen_spans(data)?;
+            writeln!(self.dest, "</{}>", tag)?;
+        }
+        writeln!(self.dest, "{:>indent$}    </tr>", " ")
     }
 
-    fn gen_code_block(&mut self, lang: &String, code: &String, indent: usize) -> Result<(), io::Error> {
-        write!(self.dest, "{:>indent$}<pre><code class=\"language-{}\">", " ", if lang == "" { "plaintext" } else { lang })?;
-        write!(self.dest, "{}", code)?;
-        writeln!(self.dest, "</code></pre>")
+    fn gen_figure(&mut self, id: &Option<String>, number: usize, caption: &Vec<Span>, block: &Block, indent: usize) -> Result<(), io::Error> {
+        // tables are captioned above and the others below
+        let (kind, above) = if matches!(block, Table { .. }) { ("Table", true) } else { ("Figure", false) };
+        match id {
+            Some(id) => writeln!(self.dest, "{:>indent$}<figure class=\"figure\" id=\"{}\">", " ", escape_attr(id))?,
+            None => writeln!(self.dest, "{:>indent$}<figure class=\"figure\">", " ")?,
+        }
+        if !above {
+            self.gen_block(block, indent + 2)?;
+        }
+        write!(self.dest, "{:>indent$}  <figcaption>{} {}: ", " ", kind, number)?;
+        self.gen_spans(caption)?;
+        writeln!(self.dest, "</figcaption>")?;
+        if above {
+            self.gen_block(block, indent + 2)?;
+        }
+        writeln!(self.dest, "{:>indent$}</figure>", " ")
+    }
+
+    fn gen_html_block(&mut self, html: &str, indent: usize) -> Result<(), io::Error> {
+        if self.options.html {
+            write!(self.dest, "{}", html)
+        } else {
+            writeln!(self.dest, "{:>indent$}<p>{}</p>", " ", escape_text(html.trim_end()))
+        }
+    }
+
+    fn gen_thematic_break(&mut self, indent: usize) -> Result<(), io::Error> {
+        writeln!(self.dest, "{:>indent$}<hr>", " ")
+    }
+
+    fn gen_math_block(&mut self, math: &str, label: &Option<String>, number: Option<usize>, indent: usize) -> Result<(), io::Error> {
+        let math = if self.options.mathml { tex_to_mathml(math, true) } else { format!("\\[{}\\]", escape_text(math)) };
+        match label {
+            Some(label) => writeln!(self.dest, "{:>indent$}<p class=\"equation\" id=\"{}\">{}<span class=\"equation-number\">{}</span></p>", " ", escape_attr(label), math, ref_text(label, number)),
+            None => writeln!(self.dest, "{:>indent$}<p>{}</p>", " ", math),
+        }
+    }
+
+    fn gen_code_block(&mut self, lang: &str, title: &Option<String>, line_numbers: bool, highlight: &[(usize, usize)], code: &str, indent: usize) -> Result<(), io::Error> {
+        // highlight.js would discard the markup of lines, so blocks with it are highlighted here regardless of the option
+        let lined = line_numbers || !highlight.is_empty();
+        let tokens = if self.options.highlight || lined { tokenize(lang, code) } else { None };
+        if lined && tokens.is_none() && !lang.is_empty() {
+            println!("line numbers and highlighted lines of {} code are shown without syntax highlighting.", lang);
+        }
+        let lines = match &tokens {
+            Some(tokens) => highlight_lines(tokens),
+            None => code.lines().map(escape_text).collect(),
+        };
+
+        let mut class = format!("language-{}", escape_attr(if lang.is_empty() { "plaintext" } else { lang }));
+        if tokens.is_some() {
+            class.push_str(" hljs");
+        }
+        if lined {
+            class.push_str(" code-lines");
+        }
+        let highlighted = if tokens.is_some() || lined { " data-highlighted=\"yes\"" } else { "" };
+
+        if title.is_none() && !lined {
+            write!(self.dest, "{:>indent$}<pre><code class=\"{}\"{}>", " ", class, highlighted)?;
+            for line in &lines {
+                writeln!(self.dest, "{}", line)?;
+            }
+            return writeln!(self.dest, "</code></pre>");
+        }
+
+        writeln!(self.dest, "{:>indent$}<figure class=\"code-block\">", " ")?;
+        if let Some(title) = title {
+            writeln!(self.dest, "{:>indent$}  <figcaption class=\"code-title\">{}</figcaption>", " ", escape_text(title))?;
+        }
+        write!(self.dest, "{:>indent$}  <pre><code class=\"{}\"{}>", " ", class, highlighted)?;
+        for (i, line) in lines.iter().enumerate() {
+            if !lined {
+                writeln!(self.dest, "{}", line)?;
+                continue;
+            }
+            let highlighted = highlight.iter().any(|&(begin, end)| (begin..=end).contains(&(i + 1)));
+            write!(self.dest, "<span class=\"{}\">", if highlighted { "line highlighted" } else { "line" })?;
+            if line_numbers {
+                write!(self.dest, "<span class=\"line-number\">{}</span>", i + 1)?;
+            }
+            writeln!(self.dest, "{}</span>", line)?;
+        }
+        writeln!(self.dest, "</code></pre>")?;
+        writeln!(self.dest, "{:>indent$}</figure>", " ")
     }
 
     fn gen_paragraph(&mut self, spans: &Vec<Span>, indent: usize) -> Result<(), io::Error> {
@@ -163,38 +344,171 @@ impl<'a> CodeGen<'a> {
     fn gen_spans(&mut self, spans: &Vec<Span>) -> Result<(), io::Error> {
         for span in spans {
             match span {
-                Link { text, url } => { self.gen_link(text, url)?; },
-                Emphasis { text } => { self.gen_emphasis(text)?; },
-                Strong { text } => { self.gen_strong(text)?; },
+                Link { text, url, title } => { self.gen_link(text, url, title)?; },
+                Span::Image { alt, url, title, width, height } => { self.gen_img(alt, url, title, width, height)?; },
+                Emphasis { spans } => { self.gen_emphasis(spans)?; },
+                Strong { spans } => { self.gen_strong(spans)?; },
+                Strikethrough { spans } => { self.gen_strikethrough(spans)?; },
                 Math { math } => { self.gen_math(math)?; },
+                Ref { label, number } => { self.gen_ref(label, *number)?; },
+                FootnoteRef { number, index } => { self.gen_footnote_ref(*number, *index)?; },
                 Code { code } => { self.gen_code(code)?; },
+                Span::Html { html } => { self.gen_raw_html(html)?; },
                 Text { text } => { self.gen_text(text)?; },
+                SoftBreak => { writeln!(self.dest)?; },
+                HardBreak => { writeln!(self.dest, "<br>")?; },
             }
         }
         Ok(())
     }
 
-    fn gen_link(&mut self, text: &String, url: &String) -> Result<(), io::Error> {
-        write!(self.dest, "<a href=\"{}\">{}</a>", *url, *text)
+    fn gen_link(&mut self, text: &Vec<Span>, url: &str, title: &Option<String>) -> Result<(), io::Error> {
+        match title {
+            Some(title) => write!(self.dest, "<a href=\"{}\" title=\"{}\"{}>", escape_url(url), escape_attr(title), self.link_rel(url))?,
+            None => write!(self.dest, "<a href=\"{}\"{}>", escape_url(url), self.link_rel(url))?,
+        }
+        self.gen_spans(text)?;
+        write!(self.dest, "</a>")
+    }
+
+    fn link_rel(&self, url: &str) -> &'static str {
+        if self.options.safe && is_external_url(url) { " rel=\"nofollow noopener\"" } else { "" }
     }
 
-    fn gen_emphasis(&mut self, text: &String) -> Result<(), io::Error> {
-        write!(self.dest, "<em>{}</em>", *text)
+    fn gen_img(&mut self, alt: &str, url: &str, title: &Option<String>, width: &Option<String>, height: &Option<String>) -> Result<(), io::Error> {
+        write!(self.dest, "<img src=\"{}\" alt=\"{}\"", escape_url(url), escape_attr(alt))?;
+        if let Some(title) = title {
+            write!(self.dest, " title=\"{}\"", escape_attr(title))?;
+        }
+        if let Some(width) = width {
+            write!(self.dest, " width=\"{}\"", escape_attr(width))?;
+        }
+        if let Some(height) = height {
+            write!(self.dest, " height=\"{}\"", escape_attr(height))?;
+        }
+        write!(self.dest, " loading=\"lazy\">")
     }
 
-    fn gen_strong(&mut self, text: &String) -> Result<(), io::Error> {
-        write!(self.dest, "<strong>{}</strong>", *text)
+    fn gen_emphasis(&mut self, spans: &Vec<Span>) -> Result<(), io::Error> {
+        write!(self.dest, "<em>")?;
+        self.gen_spans(spans)?;
+        write!(self.dest, "</em>")
     }
 
-    fn gen_math(&mut self, math: &String) -> Result<(), io::Error> {
-        write!(self.dest, "\\({}\\)", *math)
+    fn gen_strong(&mut self, spans: &Vec<Span>) -> Result<(), io::Error> {
+        write!(self.dest, "<strong>")?;
+        self.gen_spans(spans)?;
+        write!(self.dest, "</strong>")
     }
 
-    fn gen_code(&mut self, code: &String) -> Result<(), io::Error> {
-        write!(self.dest, "<code>{}</code>", *code)
+    fn gen_strikethrough(&mut self, spans: &Vec<Span>) -> Result<(), io::Error> {
+        write!(self.dest, "<del>")?;
+        self.gen_spans(spans)?;
+        write!(self.dest, "</del>")
     }
 
-    fn gen_text(&mut self, text: &String) -> Result<(), io::Error> {
-        write!(self.dest, "{}", text)
+    fn gen_math(&mut self, math: &str) -> Result<(), io::Error> {
+        if self.options.mathml {
+            write!(self.dest, "{}", tex_to_mathml(math, false))
+        } else {
+            write!(self.dest, "\\({}\\)", escape_text(math))
+        }
+    }
+
+    fn gen_ref(&mut self, label: &str, number: Option<usize>) -> Result<(), io::Error> {
+        write!(self.dest, "<a class=\"ref\" href=\"#{}\">{}</a>", escape_url(label), ref_text(label, number))
+    }
+
+    fn gen_footnote_ref(&mut self, number: usize, index: usize) -> Result<(), io::Error> {
+        write!(self.dest, "<sup class=\"footnote-ref\"><a href=\"#fn-{0}\" id=\"{1}\">{0}</a></sup>", number, footnote_ref_id(number, index))
+    }
+
+    fn gen_code(&mut self, code: &str) -> Result<(), io::Error> {
+        write!(self.dest, "<code>{}</code>", escape_text(code))
+    }
+
+    fn gen_raw_html(&mut self, html: &str) -> Result<(), io::Error> {
+        if self.options.html {
+            write!(self.dest, "{}", html)
+        } else {
+            write!(self.dest, "{}", escape_text(html))
+        }
+    }
+
+    fn gen_text(&mut self, text: &str) -> Result<(), io::Error> {
+        write!(self.dest, "{}", escape_text(text))
+    }
+}
+
+// split highlighted tokens into lines of markup, closing and reopening the tokens spanning lines
+fn highlight_lines(tokens: &Vec<Token>) -> Vec<String> {
+    let mut lines = vec![ String::new() ];
+    for &(class, text) in tokens {
+        for (i, part) in text.split('\n').enumerate() {
+            if i > 0 {
+                lines.push(String::new());
+            }
+            let line = lines.last_mut().unwrap();
+            if class.is_empty() || part.is_empty() {
+                line.push_str(&escape_text(part));
+            } else {
+                line.push_str(&format!("<span class=\"{}\">{}</span>", class, escape_text(part)));
+            }
+        }
+    }
+    // as `str::lines`, a final line ending does not start another line
+    if lines.len() > 1 && lines.last().unwrap().is_empty() {
+        lines.pop();
+    }
+    lines
+}
+
+// the id of the reference to a footnote, the first of which has no suffix
+fn footnote_ref_id(number: usize, index: usize) -> String {
+    if index == 1 { format!("fnref-{}", number) } else { format!("fnref-{}-{}", number, index) }
+}
+
+// text content, escaped so that it can never be read as markup
+pub fn escape_text(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => { res.push_str("&amp;"); },
+            '<' => { res.push_str("&lt;"); },
+            '>' => { res.push_str("&gt;"); },
+            '"' => { res.push_str("&quot;"); },
+            '\'' => { res.push_str("&#39;"); },
+            _ => { res.push(c); },
+        }
+    }
+    res
+}
+
+// a double-quoted attribute value, which additionally keeps line breaks and tabs from being normalized
+pub fn escape_attr(value: &str) -> String {
+    let mut res = String::with_capacity(value.len());
+    for c in escape_text(value).chars() {
+        match c {
+            '\n' => { res.push_str("&#10;"); },
+            '\r' => { res.push_str("&#13;"); },
+            '\t' => { res.push_str("&#9;"); },
+            _ => { res.push(c); },
+        }
+    }
+    res
+}
+
+// a URL in an attribute value, with characters not allowed in URLs percent-encoded first
+fn escape_url(url: &str) -> String {
+    let mut res = String::with_capacity(url.len());
+    let bytes = url.as_bytes();
+    for (i, &b) in bytes.iter().enumerate() {
+        let valid_percent = b == b'%' && bytes.len() > i + 2 && bytes[i + 1].is_ascii_hexdigit() && bytes[i + 2].is_ascii_hexdigit();
+        if valid_percent || (b.is_ascii_graphic() && !b"%\"<>\\^`{|}".contains(&b)) {
+            res.push(b as char);
+        } else {
+            res.push_str(&format!("%{:02X}", b));
+        }
     }
+    escape_attr(&res)
 }
\ No newline at end of file
@@ -1,41 +1,91 @@
-#[derive(Debug)]
+use std::collections::HashMap;
+use chrono::NaiveDateTime;
+
+#[derive(Clone, Debug)]
 pub enum Block {
     Header { spans: Vec<Span>, level: u32, id: String },
-    Blockquote { lines: Vec<Vec<Span>> },
+    Blockquote { blocks: Vec<Block> },
     ListElement(List),
-    Image { url: String },
+    Image { alt: String, url: String, title: Option<String>, width: Option<String>, height: Option<String> },
     LinkCard { title: String, image: Option<String>, url: String, description: Option<String>, site_name: Option<String> },
-    MathBlock { math: String },
-    CodeBlock { lang: String, code: String },
-    Table { head: Vec<Vec<String>>, body: Vec<Vec<String>> },
+    MathBlock { math: String, label: Option<String>, number: Option<usize> },
+    CodeBlock { lang: String, title: Option<String>, line_numbers: bool, highlight: Vec<(usize, usize)>, code: String },
+    Table { align: Vec<Option<Align>>, head: Vec<Vec<Vec<Span>>>, body: Vec<Vec<Vec<Span>>> },
+    Figure { id: Option<String>, number: usize, caption: Vec<Span>, block: Box<Block> },
+    Html { html: String },
+    ThematicBreak,
     Paragraph { spans: Vec<Span> },
 }
 
 #[derive(Clone, Debug)]
 pub enum Span {
-    Link { text: String, url: String },
-    Emphasis { text: String },
-    Strong { text: String },
+    Link { text: Vec<Span>, url: String, title: Option<String> },
+    Image { alt: String, url: String, title: Option<String>, width: Option<String>, height: Option<String> },
+    Emphasis { spans: Vec<Span> },
+    Strong { spans: Vec<Span> },
+    Strikethrough { spans: Vec<Span> },
     Math { math: String },
+    Ref { label: String, number: Option<usize> },
+    FootnoteRef { number: usize, index: usize },
     Code { code: String },
+    Html { html: String },
     Text { text: String },
+    SoftBreak,
+    HardBreak,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Clone, Debug)]
 pub struct List {
     pub ordered: bool,
+    pub start: u32,
+    pub marker: char,
+    pub tight: bool,
     pub items: Vec<ListItem>,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct ListItem {
-    pub spans: Vec<Span>,
-    pub list: List,
+    pub checked: Option<bool>,
+    pub blocks: Vec<Block>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Footnote {
+    pub number: usize,
+    // the number of references to this footnote
+    pub refs: usize,
+    pub blocks: Vec<Block>,
+}
+
+#[derive(Debug, Default)]
+pub struct Metadata {
+    // the first h1 header unless given in the front matter
+    pub title: String,
+    // the time of conversion is used unless given
+    pub date: Option<NaiveDateTime>,
+    pub author: Option<String>,
+    pub tags: Vec<String>,
+    pub description: Option<String>,
+    pub draft: bool,
+    // the other keys, whose lists are joined with commas
+    pub extra: HashMap<String, String>,
 }
 
 #[derive(Debug)]
 pub enum Elem {
     Title,
+    Author,
+    Description,
+    Tags,
+    Draft,
+    Meta(String),
     Year,
     Month,
     Day,
@@ -44,5 +94,6 @@ pub enum Elem {
     Second,
     Toc(usize),
     Content(usize),
+    Footnotes(usize),
     Str(String),
 }
\ No newline at end of file
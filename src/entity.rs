@@ -0,0 +1,70 @@
+// named character references commonly found in notes (a subset of the HTML5 list)
+const ENTITIES: &[(&str, char)] = &[
+    ("amp", '&'), ("lt", '<'), ("gt", '>'), ("quot", '"'), ("apos", '\''),
+    ("nbsp", '\u{a0}'), ("ensp", '\u{2002}'), ("emsp", '\u{2003}'), ("thinsp", '\u{2009}'),
+    ("shy", '\u{ad}'), ("zwnj", '\u{200c}'), ("zwj", '\u{200d}'),
+    ("copy", '©'), ("reg", '®'), ("trade", '™'), ("sect", '§'), ("para", '¶'),
+    ("deg", '°'), ("micro", 'µ'), ("middot", '·'), ("bull", '•'), ("hellip", '…'),
+    ("prime", '′'), ("Prime", '″'), ("dagger", '†'), ("Dagger", '‡'), ("permil", '‰'),
+    ("ndash", '–'), ("mdash", '—'), ("lsquo", '‘'), ("rsquo", '’'), ("sbquo", '‚'),
+    ("ldquo", '“'), ("rdquo", '”'), ("bdquo", '„'), ("laquo", '«'), ("raquo", '»'),
+    ("lsaquo", '‹'), ("rsaquo", '›'), ("iexcl", '¡'), ("iquest", '¿'),
+    ("cent", '¢'), ("pound", '£'), ("yen", '¥'), ("euro", '€'), ("curren", '¤'),
+    ("times", '×'), ("divide", '÷'), ("plusmn", '±'), ("minus", '−'), ("not", '¬'),
+    ("frac14", '¼'), ("frac12", '½'), ("frac34", '¾'), ("sup1", '¹'), ("sup2", '²'), ("sup3", '³'),
+    ("le", '≤'), ("ge", '≥'), ("ne", '≠'), ("equiv", '≡'), ("asymp", '≈'), ("sim", '∼'),
+    ("infin", '∞'), ("prop", '∝'), ("sum", '∑'), ("prod", '∏'), ("radic", '√'), ("int", '∫'),
+    ("part", '∂'), ("nabla", '∇'), ("forall", '∀'), ("exist", '∃'), ("empty", '∅'),
+    ("isin", '∈'), ("notin", '∉'), ("ni", '∋'), ("sub", '⊂'), ("sup", '⊃'), ("sube", '⊆'), ("supe", '⊇'),
+    ("cap", '∩'), ("cup", '∪'), ("and", '∧'), ("or", '∨'), ("oplus", '⊕'), ("otimes", '⊗'),
+    ("larr", '←'), ("rarr", '→'), ("uarr", '↑'), ("darr", '↓'), ("harr", '↔'),
+    ("lArr", '⇐'), ("rArr", '⇒'), ("uArr", '⇑'), ("dArr", '⇓'), ("hArr", '⇔'),
+    ("alpha", 'α'), ("beta", 'β'), ("gamma", 'γ'), ("delta", 'δ'), ("epsilon", 'ε'),
+    ("zeta", 'ζ'), ("eta", 'η'), ("theta", 'θ'), ("iota", 'ι'), ("kappa", 'κ'),
+    ("lambda", 'λ'), ("mu", 'μ'), ("nu", 'ν'), ("xi", 'ξ'), ("omicron", 'ο'),
+    ("pi", 'π'), ("rho", 'ρ'), ("sigma", 'σ'), ("tau", 'τ'), ("upsilon", 'υ'),
+    ("phi", 'φ'), ("chi", 'χ'), ("psi", 'ψ'), ("omega", 'ω'),
+    ("Gamma", 'Γ'), ("Delta", 'Δ'), ("Theta", 'Θ'), ("Lambda", 'Λ'), ("Xi", 'Ξ'),
+    ("Pi", 'Π'), ("Sigma", 'Σ'), ("Phi", 'Φ'), ("Psi", 'Ψ'), ("Omega", 'Ω'),
+    ("spades", '♠'), ("clubs", '♣'), ("hearts", '♥'), ("diams", '♦'), ("check", '✓'),
+];
+
+// split `&name;` into the name and the rest
+pub fn split_entity(chs: &str) -> Option<(&str, &str)> {
+    let chs = chs.strip_prefix('&')?;
+    let end = chs.find(';').filter(|&end| end <= 32)?;
+    let name = &chs[..end];
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '#') {
+        return None;
+    }
+    Some((name, &chs[end + 1..]))
+}
+
+pub fn decode_entity(name: &str) -> Option<char> {
+    if let Some(num) = name.strip_prefix('#') {
+        let code = match num.strip_prefix(['x', 'X']) {
+            Some(hex) if (1..=6).contains(&hex.len()) && hex.chars().all(|c| c.is_ascii_hexdigit()) => u32::from_str_radix(hex, 16).ok()?,
+            None if (1..=7).contains(&num.len()) && num.chars().all(|c| c.is_ascii_digit()) => num.parse().ok()?,
+            _ => return None,
+        };
+        // invalid code points are replaced with U+FFFD
+        return Some(char::from_u32(code).filter(|&c| c != '\0').unwrap_or('\u{fffd}'));
+    }
+    ENTITIES.iter().find(|(entity, _)| *entity == name).map(|&(_, c)| c)
+}
+
+// replace the entity references in text taken from HTML
+pub fn decode_entities(text: &str) -> String {
+    let mut res = String::new();
+    let mut chs = text;
+    while let Some(i) = chs.find('&') {
+        res.push_str(&chs[..i]);
+        chs = &chs[i..];
+        match split_entity(chs).and_then(|(name, rest)| Some((decode_entity(name)?, rest))) {
+            Some((c, rest)) => { res.push(c); chs = rest; },
+            None => { res.push('&'); chs = &chs[1..]; },
+        }
+    }
+    res.push_str(chs);
+    res
+}
\ No newline at end of file
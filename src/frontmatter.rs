@@ -0,0 +1,239 @@
+// the metadata of a document written in YAML between `---` or in TOML between `+++` at its start
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+
+use crate::data::Metadata;
+
+#[derive(Debug)]
+enum Value {
+    Str(String),
+    List(Vec<String>),
+}
+
+use Value::*;
+
+// split the front matter from the body, which is left as is without front matter
+pub fn split_front_matter(doc: &str) -> (Metadata, &str) {
+    let doc = doc.strip_prefix('\u{feff}').unwrap_or(doc);
+    let first = doc.lines().next().unwrap_or("").trim_end();
+    let delim = match first {
+        "---" => "---",
+        "+++" => "+++",
+        _ => { return (Metadata::default(), doc); },
+    };
+
+    let mut lines = Vec::new();
+    let mut rest = None;
+    let mut pos = doc.find('\n').map_or(doc.len(), |i| i + 1);
+    while pos < doc.len() {
+        let end = doc[pos..].find('\n').map_or(doc.len(), |i| pos + i + 1);
+        let line = doc[pos..end].trim_end();
+        // YAML may also be closed with `...`
+        if line == delim || (delim == "---" && line == "...") {
+            rest = Some(&doc[end..]);
+            break;
+        }
+        lines.push(line);
+        pos = end;
+    }
+    // without the closing line or with lines other than metadata, the first line is a thematic break
+    let Some(rest) = rest else {
+        return (Metadata::default(), doc);
+    };
+    let is_metadata = if delim == "---" { is_yaml_line } else { is_toml_line };
+    if !lines.iter().all(|line| is_metadata(line)) {
+        return (Metadata::default(), doc);
+    }
+
+    let values = if delim == "---" { parse_yaml(&lines) } else { parse_toml(&lines) };
+    (to_metadata(values), rest)
+}
+
+fn to_metadata(values: Vec<(String, Value)>) -> Metadata {
+    let mut meta = Metadata::default();
+    for (key, value) in values {
+        match (key.as_str(), value) {
+            ("title", Str(title)) => { meta.title = title; },
+            ("date", Str(date)) => {
+                meta.date = parse_date(&date);
+                if meta.date.is_none() {
+                    println!("invalid date in the front matter: {}", date);
+                }
+            },
+            ("author", Str(author)) => { meta.author = Some(author); },
+            ("author", List(authors)) => { meta.author = Some(authors.join(", ")); },
+            ("tags", List(tags)) => { meta.tags = tags; },
+            ("tags", Str(tags)) => { meta.tags = tags.split(',').map(|tag| tag.trim().to_string()).filter(|tag| !tag.is_empty()).collect(); },
+            ("description", Str(description)) => { meta.description = Some(description); },
+            ("draft", Str(draft)) => { meta.draft = matches!(draft.as_str(), "true" | "True" | "TRUE" | "yes" | "on"); },
+            (_, Str(value)) => { meta.extra.insert(key, value); },
+            (_, List(values)) => { meta.extra.insert(key, values.join(", ")); },
+        }
+    }
+    meta
+}
+
+// a date with an optional time, to which the offset is not applied
+fn parse_date(date: &str) -> Option<NaiveDateTime> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(date) {
+        return Some(datetime.naive_local());
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"] {
+        if let Ok(datetime) = NaiveDateTime::parse_from_str(date, format) {
+            return Some(datetime);
+        }
+    }
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").ok().and_then(|date| date.and_hms_opt(0, 0, 0))
+}
+
+// a key, an indented continuation, an item of a block sequence, a comment or a blank line
+fn is_yaml_line(line: &str) -> bool {
+    if line.trim().is_empty() || line.starts_with([' ', '\t', '#']) || line.starts_with("- ") || line == "-" {
+        return true;
+    }
+    line.split_once(':').is_some_and(|(key, value)| is_key(key.trim_end()) && (value.is_empty() || value.starts_with([' ', '\t'])))
+}
+
+// a key/value pair, a table header, a comment or a blank line
+fn is_toml_line(line: &str) -> bool {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') || (line.starts_with('[') && line.ends_with(']')) {
+        return true;
+    }
+    line.split_once('=').is_some_and(|(key, _)| is_key(key.trim()))
+}
+
+// a bare key, possibly dotted, or a quoted one
+fn is_key(key: &str) -> bool {
+    let quoted = key.len() >= 2 && ((key.starts_with('"') && key.ends_with('"')) || (key.starts_with('\'') && key.ends_with('\'')));
+    quoted || (!key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || "_-.".contains(c)))
+}
+
+// the subset of YAML used in front matter: scalars, flow and block sequences, and block scalars
+fn parse_yaml(lines: &[&str]) -> Vec<(String, Value)> {
+    let mut values = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        i += 1;
+        if line.trim().is_empty() || line.trim_start().starts_with('#') || line.starts_with([' ', '\t']) {
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else { continue };
+        let key = unquote(key.trim());
+        let value = strip_comment(value.trim());
+
+        if value.is_empty() {
+            // a block sequence of the following lines starting with `-`
+            let mut items = Vec::new();
+            while i < lines.len() && (lines[i].trim().is_empty() || lines[i].trim_start().starts_with("- ") || lines[i].trim() == "-") {
+                if let Some(item) = lines[i].trim_start().strip_prefix('-') {
+                    items.push(unquote(strip_comment(item.trim())));
+                }
+                i += 1;
+            }
+            values.push((key, if items.is_empty() { Str(String::new()) } else { List(items) }));
+        } else if value.starts_with('|') || value.starts_with('>') {
+            // a block scalar of the following indented lines, folded into one line with `>`
+            let mut block = Vec::new();
+            while i < lines.len() && (lines[i].trim().is_empty() || lines[i].starts_with([' ', '\t'])) {
+                block.push(lines[i].trim());
+                i += 1;
+            }
+            while block.last() == Some(&"") {
+                block.pop();
+            }
+            let sep = if value.starts_with('|') { "\n" } else { " " };
+            values.push((key, Str(block.join(sep))));
+        } else if let Some(items) = value.strip_prefix('[').and_then(|value| value.strip_suffix(']')) {
+            values.push((key, List(split_list(items))));
+        } else {
+            values.push((key, Str(unquote(value))));
+        }
+    }
+    values
+}
+
+// the subset of TOML used in front matter: key/value pairs, one-line arrays and tables, whose keys are prefixed
+fn parse_toml(lines: &[&str]) -> Vec<(String, Value)> {
+    let mut values = Vec::new();
+    let mut table = String::new();
+    for line in lines {
+        let line = strip_comment(line.trim());
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            table = format!("{}.", name.trim_matches(['[', ']']).trim());
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else { continue };
+        let key = format!("{}{}", table, unquote(key.trim()));
+        let value = value.trim();
+        match value.strip_prefix('[').and_then(|value| value.strip_suffix(']')) {
+            Some(items) => { values.push((key, List(split_list(items)))); },
+            None => { values.push((key, Str(unquote(value)))); },
+        }
+    }
+    values
+}
+
+// the items of a flow sequence or an array, separated by commas outside quotes
+fn split_list(items: &str) -> Vec<String> {
+    let mut res = Vec::new();
+    let mut item = String::new();
+    let mut quote = None;
+    for c in items.chars() {
+        match (quote, c) {
+            (None, ',') => { res.push(unquote(item.trim())); item.clear(); },
+            (None, '"' | '\'') => { quote = Some(c); item.push(c); },
+            (Some(q), _) if q == c => { quote = None; item.push(c); },
+            _ => { item.push(c); },
+        }
+    }
+    if !item.trim().is_empty() {
+        res.push(unquote(item.trim()));
+    }
+    res
+}
+
+// remove a comment after a value, which is not in quotes and preceded by a space
+fn strip_comment(value: &str) -> &str {
+    let mut quote = None;
+    let mut prev = ' ';
+    for (i, c) in value.char_indices() {
+        match (quote, c) {
+            (None, '#') if prev == ' ' || prev == '\t' => { return value[..i].trim_end(); },
+            (None, '"' | '\'') => { quote = Some(c); },
+            (Some(q), _) if q == c => { quote = None; },
+            _ => {},
+        }
+        prev = c;
+    }
+    value
+}
+
+// a quoted string with the escapes of double quotes, or a plain one as is
+fn unquote(value: &str) -> String {
+    if let Some(value) = value.strip_prefix('\'').and_then(|value| value.strip_suffix('\'')) {
+        return value.replace("''", "'");
+    }
+    let Some(value) = value.strip_prefix('"').and_then(|value| value.strip_suffix('"')) else {
+        return value.to_string();
+    };
+    let mut res = String::new();
+    let mut chs = value.chars();
+    while let Some(c) = chs.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+        match chs.next() {
+            Some('n') => { res.push('\n'); },
+            Some('t') => { res.push('\t'); },
+            Some(c) => { res.push(c); },
+            None => { res.push('\\'); },
+        }
+    }
+    res
+}
@@ -0,0 +1,428 @@
+// a small tokenizer for the languages used most in notes, emitting the class names of highlight.js
+// so that its themes apply to the result
+
+pub type Token<'a> = (&'static str, &'a str);
+
+pub fn tokenize<'a>(lang: &str, code: &'a str) -> Option<Vec<Token<'a>>> {
+    let syntax = match lang.to_ascii_lowercase().as_str() {
+        "rust" | "rs" => &RUST,
+        "python" | "py" => &PYTHON,
+        "c" | "h" => &C,
+        "go" | "golang" => &GO,
+        "sh" | "bash" | "shell" | "zsh" | "console" => &SHELL,
+        "json" => return Some(Lexer::new(code).json()),
+        "toml" => return Some(Lexer::new(code).toml()),
+        "html" | "xml" | "htm" => return Some(Lexer::new(code).html()),
+        _ => return None,
+    };
+    Some(Lexer::new(code).program(syntax))
+}
+
+struct Syntax {
+    keywords: &'static [&'static str],
+    types: &'static [&'static str],
+    literals: &'static [&'static str],
+    builtins: &'static [&'static str],
+    line_comment: &'static str,
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static str,
+    // `#[...]` in Rust, `#include` in C, `@decorator` in Python
+    meta: Option<char>,
+    // strings in triple quotes as in Python
+    triple_quotes: bool,
+    // strings spanning lines as in Rust and shell
+    multiline_strings: bool,
+    // lifetimes and macros as in Rust
+    lifetimes: bool,
+    // `$name` as in shell
+    variables: bool,
+}
+
+const RUST: Syntax = Syntax {
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "fn", "for", "if",
+        "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self", "Self", "static",
+        "struct", "super", "trait", "type", "unsafe", "use", "where", "while",
+    ],
+    types: &[
+        "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64", "u128", "usize", "f32", "f64",
+        "bool", "char", "str", "String", "Vec", "Option", "Result", "Box",
+    ],
+    literals: &["true", "false"],
+    builtins: &["Some", "None", "Ok", "Err"],
+    line_comment: "//",
+    block_comment: Some(("/*", "*/")),
+    quotes: "\"'",
+    meta: Some('#'),
+    triple_quotes: false,
+    multiline_strings: true,
+    lifetimes: true,
+    variables: false,
+};
+
+const PYTHON: Syntax = Syntax {
+    keywords: &[
+        "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del", "elif", "else", "except",
+        "finally", "for", "from", "global", "if", "import", "in", "is", "lambda", "nonlocal", "not", "or", "pass",
+        "raise", "return", "try", "while", "with", "yield", "match", "case",
+    ],
+    types: &["int", "float", "str", "bool", "list", "dict", "set", "tuple", "bytes", "object"],
+    literals: &["True", "False", "None"],
+    builtins: &["print", "len", "range", "open", "enumerate", "zip", "map", "filter", "sorted", "isinstance", "super", "self"],
+    line_comment: "#",
+    block_comment: None,
+    quotes: "\"'",
+    meta: Some('@'),
+    triple_quotes: true,
+    multiline_strings: false,
+    lifetimes: false,
+    variables: false,
+};
+
+const C: Syntax = Syntax {
+    keywords: &[
+        "auto", "break", "case", "const", "continue", "default", "do", "else", "enum", "extern", "for", "goto", "if",
+        "inline", "register", "restrict", "return", "sizeof", "static", "struct", "switch", "typedef", "union",
+        "volatile", "while",
+    ],
+    types: &["char", "double", "float", "int", "long", "short", "signed", "unsigned", "void", "bool", "size_t", "FILE"],
+    literals: &["NULL", "true", "false"],
+    builtins: &["printf", "scanf", "malloc", "free", "memcpy", "memset", "strlen"],
+    line_comment: "//",
+    block_comment: Some(("/*", "*/")),
+    quotes: "\"'",
+    meta: Some('#'),
+    triple_quotes: false,
+    multiline_strings: false,
+    lifetimes: false,
+    variables: false,
+};
+
+const GO: Syntax = Syntax {
+    keywords: &[
+        "break", "case", "chan", "const", "continue", "default", "defer", "else", "fallthrough", "for", "func", "go",
+        "goto", "if", "import", "interface", "map", "package", "range", "return", "select", "struct", "switch", "type", "var",
+    ],
+    types: &[
+        "bool", "byte", "complex64", "complex128", "error", "float32", "float64", "int", "int8", "int16", "int32", "int64",
+        "rune", "string", "uint", "uint8", "uint16", "uint32", "uint64", "uintptr", "any",
+    ],
+    literals: &["true", "false", "nil", "iota"],
+    builtins: &["append", "cap", "close", "copy", "delete", "len", "make", "new", "panic", "print", "println", "recover"],
+    line_comment: "//",
+    block_comment: Some(("/*", "*/")),
+    quotes: "\"'`",
+    meta: None,
+    triple_quotes: false,
+    multiline_strings: false,
+    lifetimes: false,
+    variables: false,
+};
+
+const SHELL: Syntax = Syntax {
+    keywords: &[
+        "if", "then", "else", "elif", "fi", "case", "esac", "for", "while", "until", "do", "done", "in", "function",
+        "select", "return", "exit", "local", "export", "readonly",
+    ],
+    types: &[],
+    literals: &["true", "false"],
+    builtins: &["echo", "cd", "pwd", "printf", "read", "source", "test", "set", "unset", "shift", "alias", "eval", "exec", "trap"],
+    line_comment: "#",
+    block_comment: None,
+    quotes: "\"'",
+    meta: None,
+    triple_quotes: false,
+    multiline_strings: true,
+    lifetimes: false,
+    variables: true,
+};
+
+struct Lexer<'a> {
+    code: &'a str,
+    pos: usize,
+    // the start of the plain text not yet pushed
+    plain: usize,
+    tokens: Vec<Token<'a>>,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(code: &'a str) -> Self {
+        Lexer { code, pos: 0, plain: 0, tokens: Vec::new() }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.code[self.pos..]
+    }
+
+    fn prev(&self) -> Option<char> {
+        self.code[..self.pos].chars().next_back()
+    }
+
+    fn at_line_start(&self) -> bool {
+        let before = self.code[..self.pos].trim_end_matches([' ', '\t']);
+        before.is_empty() || before.ends_with('\n')
+    }
+
+    fn push(&mut self, class: &'static str, len: usize) {
+        if self.plain < self.pos {
+            self.tokens.push(("", &self.code[self.plain..self.pos]));
+        }
+        self.tokens.push((class, &self.code[self.pos..self.pos + len]));
+        self.pos += len;
+        self.plain = self.pos;
+    }
+
+    fn skip(&mut self) {
+        self.pos += self.rest().chars().next().map_or(0, char::len_utf8);
+    }
+
+    fn finish(mut self) -> Vec<Token<'a>> {
+        if self.plain < self.code.len() {
+            self.tokens.push(("", &self.code[self.plain..]));
+        }
+        self.tokens
+    }
+
+    fn program(mut self, syntax: &Syntax) -> Vec<Token<'a>> {
+        while let Some(c) = self.rest().chars().next() {
+            let rest = self.rest();
+
+            // comments
+            if !syntax.line_comment.is_empty() && rest.starts_with(syntax.line_comment) {
+                // `#` starts a comment in shell only at the beginning of a word
+                if !syntax.variables || self.prev().is_none_or(char::is_whitespace) {
+                    self.push("hljs-comment", line_len(rest));
+                    continue;
+                }
+            }
+            if let Some((open, close)) = syntax.block_comment {
+                if let Some(comment) = rest.strip_prefix(open) {
+                    let len = comment.find(close).map_or(rest.len(), |i| open.len() + i + close.len());
+                    self.push("hljs-comment", len);
+                    continue;
+                }
+            }
+
+            // attributes, preprocessor directives and decorators
+            if Some(c) == syntax.meta {
+                if c == '#' && (rest.starts_with("#[") || rest.starts_with("#![")) {
+                    self.push("hljs-meta", rest.find(']').map_or(rest.len(), |i| i + 1));
+                    continue;
+                }
+                if c == '#' && self.at_line_start() || c == '@' {
+                    self.push("hljs-meta", if c == '#' { line_len(rest) } else { word_len(&rest[1..]) + 1 });
+                    continue;
+                }
+            }
+
+            // variables of shell
+            if syntax.variables && c == '$' {
+                let len = if rest.starts_with("${") {
+                    rest.find('}').map_or(rest.len(), |i| i + 1)
+                } else {
+                    1 + rest[1..].find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len() - 1).max(1)
+                };
+                self.push("hljs-variable", len.min(rest.len()));
+                continue;
+            }
+
+            // strings
+            if syntax.quotes.contains(c) {
+                // a quote in Rust starts a lifetime rather than a character unless it is closed soon
+                if c == '\'' && syntax.lifetimes && !is_char_literal(rest) {
+                    self.push("hljs-symbol", 1 + word_len(&rest[1..]));
+                    continue;
+                }
+                self.push("hljs-string", string_len(rest, syntax.triple_quotes, syntax.multiline_strings));
+                continue;
+            }
+
+            // numbers
+            if c.is_ascii_digit() && !self.prev().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+                self.push("hljs-number", number_len(rest));
+                continue;
+            }
+
+            // words
+            if c.is_alphabetic() || c == '_' {
+                let len = word_len(rest);
+                let word = &rest[..len];
+                let after = &rest[len..];
+                let class = if syntax.keywords.contains(&word) {
+                    "hljs-keyword"
+                } else if syntax.types.contains(&word) {
+                    "hljs-type"
+                } else if syntax.literals.contains(&word) {
+                    "hljs-literal"
+                } else if syntax.builtins.contains(&word) || syntax.lifetimes && after.starts_with('!') && !after.starts_with("!=") {
+                    "hljs-built_in"
+                } else if after.starts_with('(') {
+                    "hljs-title"
+                } else {
+                    ""
+                };
+                if class.is_empty() {
+                    self.pos += len;
+                } else {
+                    self.push(class, len);
+                }
+                continue;
+            }
+
+            self.skip();
+        }
+        self.finish()
+    }
+
+    fn json(mut self) -> Vec<Token<'a>> {
+        while let Some(c) = self.rest().chars().next() {
+            let rest = self.rest();
+            if c == '"' {
+                let len = string_len(rest, false, false);
+                // a string followed by `:` is a key
+                let class = if rest[len..].trim_start().starts_with(':') { "hljs-attr" } else { "hljs-string" };
+                self.push(class, len);
+            } else if c.is_ascii_digit() {
+                self.push("hljs-number", number_len(rest));
+            } else if c == '-' && rest[1..].starts_with(|c: char| c.is_ascii_digit()) {
+                self.push("hljs-number", 1 + number_len(&rest[1..]));
+            } else if c.is_alphabetic() {
+                let len = word_len(rest);
+                if ["true", "false", "null"].contains(&&rest[..len]) {
+                    self.push("hljs-literal", len);
+                } else {
+                    self.pos += len;
+                }
+            } else {
+                self.skip();
+            }
+        }
+        self.finish()
+    }
+
+    fn toml(mut self) -> Vec<Token<'a>> {
+        while let Some(c) = self.rest().chars().next() {
+            let rest = self.rest();
+            if c == '#' {
+                self.push("hljs-comment", line_len(rest));
+            } else if c == '[' && self.at_line_start() {
+                self.push("hljs-section", rest.find(']').map_or(line_len(rest), |i| i + 1 + usize::from(rest[i + 1..].starts_with(']'))));
+            } else if self.at_line_start() && !c.is_whitespace() && c != '=' && rest[..line_len(rest)].contains('=') {
+                // a key at the beginning of a line
+                let eq = rest.find('=').unwrap();
+                self.push("hljs-attr", rest[..eq].trim_end().len());
+            } else if c == '"' || c == '\'' {
+                self.push("hljs-string", string_len(rest, true, false));
+            } else if c.is_ascii_digit() || (c == '-' || c == '+') && rest[1..].starts_with(|c: char| c.is_ascii_digit()) {
+                let len = 1 + rest[1..].find(|c: char| !(c.is_alphanumeric() || "_.:-+".contains(c))).unwrap_or(rest.len() - 1);
+                self.push("hljs-number", len);
+            } else if c.is_alphabetic() {
+                let len = word_len(rest);
+                if ["true", "false", "inf", "nan"].contains(&&rest[..len]) {
+                    self.push("hljs-literal", len);
+                } else {
+                    self.pos += len;
+                }
+            } else {
+                self.skip();
+            }
+        }
+        self.finish()
+    }
+
+    fn html(mut self) -> Vec<Token<'a>> {
+        while let Some(c) = self.rest().chars().next() {
+            let rest = self.rest();
+            if rest.starts_with("<!--") {
+                self.push("hljs-comment", rest.find("-->").map_or(rest.len(), |i| i + 3));
+            } else if c == '<' && rest[1..].starts_with(|c: char| c.is_alphabetic() || c == '/' || c == '!' || c == '?') {
+                // the tag name, then attributes up to `>`
+                let name = rest[1..].trim_start_matches(['/', '!', '?']);
+                let len = rest.len() - name.len() + word_len_with(name, "-:");
+                self.push("hljs-name", len);
+                while let Some(c) = self.rest().chars().next() {
+                    let rest = self.rest();
+                    if c == '>' || rest.starts_with("/>") || rest.starts_with("?>") {
+                        break;
+                    }
+                    if c == '"' || c == '\'' {
+                        self.push("hljs-string", string_len(rest, false, true));
+                    } else if c.is_alphabetic() {
+                        self.push("hljs-attr", word_len_with(rest, "-:"));
+                    } else {
+                        self.skip();
+                    }
+                }
+            } else if c == '&' {
+                let len = rest.find(';').filter(|&i| i <= 32 && !rest[1..i].contains(char::is_whitespace)).map_or(0, |i| i + 1);
+                if len > 0 {
+                    self.push("hljs-symbol", len);
+                } else {
+                    self.skip();
+                }
+            } else {
+                self.skip();
+            }
+        }
+        self.finish()
+    }
+}
+
+fn line_len(chs: &str) -> usize {
+    chs.find('\n').unwrap_or(chs.len())
+}
+
+fn word_len(chs: &str) -> usize {
+    word_len_with(chs, "")
+}
+
+fn word_len_with(chs: &str, extra: &str) -> usize {
+    chs.find(|c: char| !(c.is_alphanumeric() || c == '_' || extra.contains(c))).unwrap_or(chs.len())
+}
+
+fn number_len(chs: &str) -> usize {
+    let hex = chs.starts_with("0x") || chs.starts_with("0X");
+    let mut len = 0;
+    for (i, c) in chs.char_indices() {
+        // stop before a range such as `0..10` or a method call
+        if c == '.' && !chs[i + 1..].starts_with(|c: char| c.is_ascii_digit()) {
+            break;
+        }
+        // the sign of an exponent
+        let exponent = (c == '-' || c == '+') && !hex && chs[..i].ends_with(['e', 'E']);
+        if !(c.is_ascii_alphanumeric() || c == '_' || c == '.' || exponent) {
+            break;
+        }
+        len = i + 1;
+    }
+    len
+}
+
+// the length of a string literal, with triple quotes and line breaks if allowed
+fn string_len(chs: &str, triple: bool, multiline: bool) -> usize {
+    let quote = chs.chars().next().unwrap();
+    let delim = quote.to_string().repeat(3);
+    if triple && chs.starts_with(&delim) {
+        return chs[3..].find(&delim).map_or(chs.len(), |i| i + 6);
+    }
+    let mut escaped = false;
+    for (i, c) in chs.char_indices().skip(1) {
+        match c {
+            _ if escaped => { escaped = false; },
+            '\\' if quote != '`' => { escaped = true; },
+            '\n' if quote != '`' && !multiline => { return i; },
+            _ if c == quote => { return i + 1; },
+            _ => {},
+        }
+    }
+    chs.len()
+}
+
+fn is_char_literal(chs: &str) -> bool {
+    let mut chars = chs.chars().skip(1);
+    match chars.next() {
+        Some('\\') => true,
+        Some(_) => chars.next() == Some('\''),
+        None => false,
+    }
+}
\ No newline at end of file
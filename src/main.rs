@@ -1,6 +1,11 @@
 pub mod data;
+pub mod entity;
+pub mod frontmatter;
+pub mod highlight;
+pub mod mathml;
 pub mod multiset;
 pub mod parser;
+pub mod sanitize;
 pub mod template;
 pub mod codegen;
 
@@ -9,21 +14,32 @@ use std::fs::{self, File};
 
 use crate::parser::parse_markdown;
 use crate::template::read_template;
-use crate::codegen::gen_html;
+use crate::codegen::{gen_html, Options};
 
 fn main(){
-    let args: Vec<String> = env::args().collect();
+    let (flags, args): (Vec<String>, Vec<String>) = env::args().partition(|arg| arg.starts_with("--"));
+    let mut options = Options { highlight: false, mathml: false, html: true, safe: false };
+    for flag in &flags {
+        match flag.as_str() {
+            "--highlight" => { options.highlight = true; },
+            "--mathml" => { options.mathml = true; },
+            "--no-html" => { options.html = false; },
+            "--safe" => { options.safe = true; },
+            _ => { println!("unknown option: {}", flag); return; },
+        }
+    }
+
     let temp_path = &args[1];
     let src_path = &args[2];
     let dest_path = &format!("{}.html", src_path.trim_end_matches(".md"));
-    let dest_path = if args.len() <= 3 { &dest_path } else { &args[3] };
+    let dest_path = if args.len() <= 3 { dest_path } else { &args[3] };
 
     let Ok(doc) = fs::read_to_string(src_path) else {
         println!("could not open the source file.");
         return;
     };
 
-    let (title, toc, content) = parse_markdown(&doc);
+    let (meta, toc, content, footnotes) = parse_markdown(&doc);
 
     let Ok(temp) = read_template(temp_path) else {
         println!("could not open or read the template file.");
@@ -35,7 +51,7 @@ fn main(){
         return;
     };
     
-    let Ok(_) = gen_html(&mut dest, &title, &toc, &content, &temp) else {
+    let Ok(_) = gen_html(&mut dest, &meta, &toc, &content, &footnotes, &temp, &options) else {
         println!("could not write to the destination file.");
         return;
     };
@@ -0,0 +1,482 @@
+// conversion of the commonly used subset of LaTeX into MathML, which browsers render natively
+
+use crate::codegen::escape_text;
+
+pub fn tex_to_mathml(tex: &str, display: bool) -> String {
+    let mut parser = TexParser { chs: tex, display, in_table: 0 };
+    let mut row = parser.parse_row();
+    // a stray `}`, `\right` or `\end` is shown as an error, and the rest is still converted
+    while let Some(token) = parser.next() {
+        let text = match token {
+            Command("end") => format!("\\end{{{}}}", parser.parse_text_arg()),
+            Command(name) => format!("\\{}", name),
+            Char(c) => c.to_string(),
+        };
+        row.push(format!("<merror><mtext>{}</mtext></merror>", escape_text(&text)));
+        row.extend(parser.parse_row());
+    }
+    format!(
+        "<math{}><semantics><mrow>{}</mrow><annotation encoding=\"application/x-tex\">{}</annotation></semantics></math>",
+        if display { " display=\"block\"" } else { "" },
+        row.concat(),
+        escape_text(tex.trim()),
+    )
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Token<'a> {
+    Command(&'a str),
+    Char(char),
+}
+
+use Token::*;
+
+struct TexParser<'a> {
+    chs: &'a str,
+    display: bool,
+    // the depth of environments, in which `&` and `\\` separate cells
+    in_table: usize,
+}
+
+impl<'a> TexParser<'a> {
+    fn peek(&mut self) -> Option<Token<'a>> {
+        self.chs = self.chs.trim_start();
+        let mut chars = self.chs.chars();
+        match chars.next()? {
+            '\\' => {
+                let rest = chars.as_str();
+                let len = rest.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(rest.len());
+                // a control symbol such as `\,` or `\{` is a single character
+                let len = if len == 0 { rest.chars().next().map_or(0, char::len_utf8) } else { len };
+                Some(Command(&rest[..len]))
+            },
+            c => Some(Char(c)),
+        }
+    }
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        let token = self.peek()?;
+        let len = match token {
+            Command(name) => name.len() + 1,
+            Char(c) => c.len_utf8(),
+        };
+        self.chs = &self.chs[len..];
+        Some(token)
+    }
+
+    fn next_if(&mut self, token: Token) -> bool {
+        if self.peek() == Some(token) {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    // the nodes up to the end of a group, a `\right`, or a cell of an environment
+    fn parse_row(&mut self) -> Vec<String> {
+        let mut nodes = Vec::new();
+        while let Some(token) = self.peek() {
+            match token {
+                Char('}') | Command("right") | Command("end") => break,
+                Char('&') | Command("\\") if self.in_table > 0 => break,
+                Char('&') | Command("\\") => { self.next(); },
+                _ => { nodes.extend(self.parse_scripted()); },
+            }
+        }
+        nodes
+    }
+
+    // an argument in braces, or a single token
+    fn parse_arg(&mut self) -> String {
+        match self.peek() {
+            Some(Char('{')) => {
+                self.next();
+                let row = self.parse_row();
+                self.next_if(Char('}'));
+                mrow(row)
+            },
+            Some(Char(c)) if c.is_ascii_digit() => {
+                self.next();
+                format!("<mn>{}</mn>", c)
+            },
+            Some(_) => self.parse_atom().unwrap_or_default(),
+            None => String::new(),
+        }
+    }
+
+    // the raw text of an argument in braces
+    fn parse_text_arg(&mut self) -> &'a str {
+        if !self.next_if(Char('{')) {
+            return "";
+        }
+        let mut depth = 0;
+        for (i, c) in self.chs.char_indices() {
+            match c {
+                '{' => { depth += 1; },
+                '}' if depth == 0 => {
+                    let text = &self.chs[..i];
+                    self.chs = &self.chs[i + 1..];
+                    return text;
+                },
+                '}' => { depth -= 1; },
+                _ => {},
+            }
+        }
+        let text = self.chs;
+        self.chs = "";
+        text
+    }
+
+    fn parse_scripted(&mut self) -> Option<String> {
+        let limits = matches!(self.peek(), Some(Command(name)) if LIMIT_OPERATORS.contains(&name) || self.display && LARGE_OPERATORS.iter().any(|&(op, _)| op == name && !op.contains("int")));
+        let base = self.parse_atom()?;
+        self.next_if(Command("limits"));
+        self.next_if(Command("nolimits"));
+
+        let mut sub = None;
+        let mut sup = None;
+        let mut primes = String::new();
+        loop {
+            if self.next_if(Char('_')) {
+                sub = Some(self.parse_arg());
+            } else if self.next_if(Char('^')) {
+                sup = Some(self.parse_arg());
+            } else if self.next_if(Char('\'')) {
+                primes.push('′');
+            } else {
+                break;
+            }
+        }
+        if !primes.is_empty() {
+            let prime = format!("<mo>{}</mo>", primes);
+            sup = Some(match sup {
+                Some(sup) => format!("<mrow>{}{}</mrow>", prime, sup),
+                None => prime,
+            });
+        }
+
+        let (under, over, both) = if limits { ("munder", "mover", "munderover") } else { ("msub", "msup", "msubsup") };
+        Some(match (sub, sup) {
+            (None, None) => base,
+            (Some(sub), None) => format!("<{0}>{1}{2}</{0}>", under, base, sub),
+            (None, Some(sup)) => format!("<{0}>{1}{2}</{0}>", over, base, sup),
+            (Some(sub), Some(sup)) => format!("<{0}>{1}{2}{3}</{0}>", both, base, sub, sup),
+        })
+    }
+
+    fn parse_atom(&mut self) -> Option<String> {
+        match self.next()? {
+            Char('{') => {
+                let row = self.parse_row();
+                self.next_if(Char('}'));
+                Some(mrow(row))
+            },
+            Char('~') => Some(String::from("<mtext>&#160;</mtext>")),
+            Char(c) if c.is_ascii_digit() || c == '.' && self.chs.starts_with(|c: char| c.is_ascii_digit()) => {
+                let mut num = c.to_string();
+                while let Some(d) = self.chs.chars().next().filter(|&d| d.is_ascii_digit() || d == '.' && self.chs[1..].starts_with(|c: char| c.is_ascii_digit())) {
+                    num.push(d);
+                    self.chs = &self.chs[1..];
+                }
+                Some(format!("<mn>{}</mn>", num))
+            },
+            Char(c) if c.is_alphabetic() => Some(format!("<mi>{}</mi>", escape_text(&c.to_string()))),
+            Char(c) => Some(mo(match c {
+                '-' => "−",
+                '*' => "∗",
+                _ => return Some(format!("<mo>{}</mo>", escape_text(&c.to_string()))),
+            })),
+            Command(name) => self.parse_command(name),
+        }
+    }
+
+    fn parse_command(&mut self, name: &'a str) -> Option<String> {
+        if let Some(&(_, c)) = GREEK.iter().find(|&&(greek, _)| greek == name) {
+            // capital Greek letters are upright
+            let variant = if c.is_uppercase() { " mathvariant=\"normal\"" } else { "" };
+            return Some(format!("<mi{}>{}</mi>", variant, c));
+        }
+        if let Some(&(_, c)) = SYMBOLS.iter().find(|&&(symbol, _)| symbol == name) {
+            return Some(format!("<mi>{}</mi>", c));
+        }
+        if let Some(&(_, c)) = OPERATORS.iter().find(|&&(op, _)| op == name) {
+            return Some(mo(c));
+        }
+        if let Some(&(_, c)) = LARGE_OPERATORS.iter().find(|&&(op, _)| op == name) {
+            return Some(format!("<mo largeop=\"true\">{}</mo>", c));
+        }
+        if FUNCTIONS.contains(&name) || LIMIT_OPERATORS.contains(&name) {
+            return Some(format!("<mi>{}</mi>", name));
+        }
+        if let Some(&(_, width)) = SPACES.iter().find(|&&(space, _)| space == name) {
+            return Some(format!("<mspace width=\"{}\"></mspace>", width));
+        }
+        if let Some(&(_, accent, over)) = ACCENTS.iter().find(|&&(acc, _, _)| acc == name) {
+            let base = self.parse_arg();
+            let stretchy = if name.starts_with("wide") || name.starts_with("over") || name.starts_with("under") { " stretchy=\"true\"" } else { "" };
+            return Some(if over {
+                format!("<mover accent=\"true\">{}<mo{}>{}</mo></mover>", base, stretchy, accent)
+            } else {
+                format!("<munder accentunder=\"true\">{}<mo{}>{}</mo></munder>", base, stretchy, accent)
+            });
+        }
+
+        Some(match name {
+            "frac" | "dfrac" | "tfrac" => {
+                let num = self.parse_arg();
+                let den = self.parse_arg();
+                format!("<mfrac>{}{}</mfrac>", num, den)
+            },
+            "binom" => {
+                let n = self.parse_arg();
+                let k = self.parse_arg();
+                format!("<mrow>{}<mfrac linethickness=\"0\">{}{}</mfrac>{}</mrow>", mo("("), n, k, mo(")"))
+            },
+            "sqrt" => {
+                let index = if self.next_if(Char('[')) {
+                    let mut index = Vec::new();
+                    while !matches!(self.peek(), None | Some(Char(']'))) {
+                        index.extend(self.parse_scripted());
+                    }
+                    self.next_if(Char(']'));
+                    Some(mrow(index))
+                } else {
+                    None
+                };
+                let radicand = self.parse_arg();
+                match index {
+                    Some(index) => format!("<mroot>{}{}</mroot>", radicand, index),
+                    None => format!("<msqrt>{}</msqrt>", radicand),
+                }
+            },
+            "left" => {
+                let open = self.parse_delimiter();
+                let row = self.parse_row();
+                self.next_if(Command("right"));
+                let close = self.parse_delimiter();
+                format!("<mrow>{}{}{}</mrow>", fence(&open), row.concat(), fence(&close))
+            },
+            "middle" | "big" | "Big" | "bigg" | "Bigg" | "bigl" | "bigr" | "Bigl" | "Bigr" => {
+                let delim = self.parse_delimiter();
+                if delim.is_empty() { String::new() } else { mo(&delim) }
+            },
+            "text" | "textrm" | "mbox" => format!("<mtext>{}</mtext>", escape_text(self.parse_text_arg())),
+            "mathrm" | "operatorname" | "textup" => format!("<mi mathvariant=\"normal\">{}</mi>", escape_text(self.parse_text_arg().trim())),
+            "mathbf" | "textbf" => styled(self.parse_text_arg(), Style::Bold),
+            "mathbb" => styled(self.parse_text_arg(), Style::DoubleStruck),
+            "mathcal" | "mathscr" => styled(self.parse_text_arg(), Style::Script),
+            "mathfrak" => styled(self.parse_text_arg(), Style::Fraktur),
+            "mathit" | "textit" => format!("<mi>{}</mi>", escape_text(self.parse_text_arg().trim())),
+            "boldsymbol" | "bm" => format!("<mstyle mathvariant=\"bold\">{}</mstyle>", self.parse_arg()),
+            "begin" => {
+                let env = self.parse_text_arg();
+                self.parse_environment(env)
+            },
+            "{" => mo("{"),
+            "}" => mo("}"),
+            "|" => mo("‖"),
+            "\\" | "displaystyle" | "textstyle" | "nonumber" | "notag" | "limits" | "nolimits" => String::new(),
+            "label" | "tag" => {
+                self.parse_text_arg();
+                String::new()
+            },
+            _ => format!("<mtext>\\{}</mtext>", escape_text(name)),
+        })
+    }
+
+    // a delimiter after `\left`, `\right` or `\big`
+    fn parse_delimiter(&mut self) -> String {
+        match self.next() {
+            Some(Char('.')) | None => String::new(),
+            Some(Char(c)) => c.to_string(),
+            Some(Command("{")) => String::from("{"),
+            Some(Command("}")) => String::from("}"),
+            Some(Command("|")) => String::from("‖"),
+            Some(Command(name)) => OPERATORS.iter().find(|&&(op, _)| op == name).map_or(String::new(), |&(_, c)| c.to_string()),
+        }
+    }
+
+    fn parse_environment(&mut self, env: &str) -> String {
+        let name = env.trim_end_matches('*');
+        // the column alignment of the environments of equations alternates between right and left
+        let align = match name {
+            "align" | "aligned" | "split" | "eqnarray" | "alignat" => " columnalign=\"right left right left right left\" columnspacing=\"0em 1em\"",
+            "cases" | "array" => " columnalign=\"left left\"",
+            "gather" | "gathered" | "equation" | "multline" => " columnalign=\"center\"",
+            _ => "",
+        };
+        if name == "array" {
+            // the column specification is not needed
+            self.parse_text_arg();
+        }
+
+        self.in_table += 1;
+        let mut rows = Vec::new();
+        loop {
+            let mut cells = Vec::new();
+            loop {
+                let cell = self.parse_row();
+                cells.push(format!("<mtd>{}</mtd>", mrow(cell)));
+                if !self.next_if(Char('&')) {
+                    break;
+                }
+            }
+            let end_of_row = self.next_if(Command("\\"));
+            // a trailing `\\` does not make an empty row
+            if !(cells.len() == 1 && cells[0] == "<mtd><mrow></mrow></mtd>" && !end_of_row) || rows.is_empty() {
+                rows.push(format!("<mtr>{}</mtr>", cells.concat()));
+            }
+            if !end_of_row {
+                break;
+            }
+        }
+        self.in_table -= 1;
+        if self.next_if(Command("end")) {
+            self.parse_text_arg();
+        }
+
+        let table = format!("<mtable{}>{}</mtable>", align, rows.concat());
+        let (open, close) = match name {
+            "pmatrix" => ("(", ")"),
+            "bmatrix" => ("[", "]"),
+            "Bmatrix" => ("{", "}"),
+            "vmatrix" => ("|", "|"),
+            "Vmatrix" => ("‖", "‖"),
+            "cases" => ("{", ""),
+            _ => return table,
+        };
+        format!("<mrow>{}{}{}</mrow>", fence(open), table, fence(close))
+    }
+}
+
+fn mo(op: &str) -> String {
+    format!("<mo>{}</mo>", escape_text(op))
+}
+
+fn fence(delim: &str) -> String {
+    if delim.is_empty() {
+        String::new()
+    } else {
+        format!("<mo fence=\"true\" stretchy=\"true\">{}</mo>", escape_text(delim))
+    }
+}
+
+fn mrow(nodes: Vec<String>) -> String {
+    if nodes.len() == 1 {
+        nodes.into_iter().next().unwrap()
+    } else {
+        format!("<mrow>{}</mrow>", nodes.concat())
+    }
+}
+
+enum Style {
+    Bold,
+    DoubleStruck,
+    Script,
+    Fraktur,
+}
+
+// browsers do not support `mathvariant` except `normal`, so the styled letters of Unicode are used
+fn styled(text: &str, style: Style) -> String {
+    let mut res = String::new();
+    for c in text.trim().chars() {
+        let styled = match (&style, c) {
+            (Style::DoubleStruck, 'C') => Some('ℂ'),
+            (Style::DoubleStruck, 'H') => Some('ℍ'),
+            (Style::DoubleStruck, 'N') => Some('ℕ'),
+            (Style::DoubleStruck, 'P') => Some('ℙ'),
+            (Style::DoubleStruck, 'Q') => Some('ℚ'),
+            (Style::DoubleStruck, 'R') => Some('ℝ'),
+            (Style::DoubleStruck, 'Z') => Some('ℤ'),
+            (Style::Script, 'B') => Some('ℬ'),
+            (Style::Script, 'E') => Some('ℰ'),
+            (Style::Script, 'F') => Some('ℱ'),
+            (Style::Script, 'H') => Some('ℋ'),
+            (Style::Script, 'I') => Some('ℐ'),
+            (Style::Script, 'L') => Some('ℒ'),
+            (Style::Script, 'M') => Some('ℳ'),
+            (Style::Script, 'R') => Some('ℛ'),
+            (Style::Fraktur, 'C') => Some('ℭ'),
+            (Style::Fraktur, 'H') => Some('ℌ'),
+            (Style::Fraktur, 'I') => Some('ℑ'),
+            (Style::Fraktur, 'R') => Some('ℜ'),
+            (Style::Fraktur, 'Z') => Some('ℨ'),
+            (_, 'A'..='Z') | (_, 'a'..='z') | (Style::Bold | Style::DoubleStruck, '0'..='9') => {
+                let (upper, digit) = match style {
+                    Style::Bold => (0x1d400, 0x1d7ce),
+                    Style::DoubleStruck => (0x1d538, 0x1d7d8),
+                    Style::Script => (0x1d49c, 0),
+                    Style::Fraktur => (0x1d504, 0),
+                };
+                let code = match c {
+                    'A'..='Z' => upper + (c as u32 - 'A' as u32),
+                    'a'..='z' => upper + 26 + (c as u32 - 'a' as u32),
+                    _ => digit + (c as u32 - '0' as u32),
+                };
+                char::from_u32(code)
+            },
+            _ => None,
+        };
+        res.push(styled.unwrap_or(c));
+    }
+    format!("<mi>{}</mi>", escape_text(&res))
+}
+
+const GREEK: &[(&str, char)] = &[
+    ("alpha", 'α'), ("beta", 'β'), ("gamma", 'γ'), ("delta", 'δ'), ("epsilon", 'ϵ'), ("varepsilon", 'ε'),
+    ("zeta", 'ζ'), ("eta", 'η'), ("theta", 'θ'), ("vartheta", 'ϑ'), ("iota", 'ι'), ("kappa", 'κ'),
+    ("lambda", 'λ'), ("mu", 'μ'), ("nu", 'ν'), ("xi", 'ξ'), ("pi", 'π'), ("varpi", 'ϖ'), ("rho", 'ρ'),
+    ("varrho", 'ϱ'), ("sigma", 'σ'), ("varsigma", 'ς'), ("tau", 'τ'), ("upsilon", 'υ'), ("phi", 'ϕ'),
+    ("varphi", 'φ'), ("chi", 'χ'), ("psi", 'ψ'), ("omega", 'ω'),
+    ("Gamma", 'Γ'), ("Delta", 'Δ'), ("Theta", 'Θ'), ("Lambda", 'Λ'), ("Xi", 'Ξ'), ("Pi", 'Π'),
+    ("Sigma", 'Σ'), ("Upsilon", 'Υ'), ("Phi", 'Φ'), ("Psi", 'Ψ'), ("Omega", 'Ω'),
+];
+
+const SYMBOLS: &[(&str, char)] = &[
+    ("infty", '∞'), ("partial", '∂'), ("nabla", '∇'), ("hbar", 'ℏ'), ("ell", 'ℓ'), ("emptyset", '∅'),
+    ("varnothing", '∅'), ("Re", 'ℜ'), ("Im", 'ℑ'), ("aleph", 'ℵ'), ("angle", '∠'), ("triangle", '△'),
+    ("prime", '′'), ("dagger", '†'), ("imath", 'ı'), ("jmath", 'ȷ'),
+];
+
+const OPERATORS: &[(&str, &str)] = &[
+    ("pm", "±"), ("mp", "∓"), ("times", "×"), ("div", "÷"), ("cdot", "⋅"), ("ast", "∗"), ("star", "⋆"),
+    ("circ", "∘"), ("bullet", "∙"), ("oplus", "⊕"), ("otimes", "⊗"), ("leq", "≤"), ("le", "≤"), ("geq", "≥"),
+    ("ge", "≥"), ("neq", "≠"), ("ne", "≠"), ("approx", "≈"), ("equiv", "≡"), ("sim", "∼"), ("simeq", "≃"),
+    ("cong", "≅"), ("propto", "∝"), ("ll", "≪"), ("gg", "≫"), ("in", "∈"), ("notin", "∉"), ("ni", "∋"),
+    ("subset", "⊂"), ("supset", "⊃"), ("subseteq", "⊆"), ("supseteq", "⊇"), ("cup", "∪"), ("cap", "∩"),
+    ("setminus", "∖"), ("wedge", "∧"), ("land", "∧"), ("vee", "∨"), ("lor", "∨"), ("neg", "¬"), ("lnot", "¬"),
+    ("forall", "∀"), ("exists", "∃"), ("to", "→"), ("rightarrow", "→"), ("leftarrow", "←"), ("gets", "←"),
+    ("leftrightarrow", "↔"), ("Rightarrow", "⇒"), ("Leftarrow", "⇐"), ("Leftrightarrow", "⇔"),
+    ("iff", "⟺"), ("implies", "⟹"), ("mapsto", "↦"), ("uparrow", "↑"), ("downarrow", "↓"),
+    ("longrightarrow", "⟶"), ("longleftarrow", "⟵"), ("ldots", "…"), ("dots", "…"), ("cdots", "⋯"),
+    ("vdots", "⋮"), ("ddots", "⋱"), ("langle", "⟨"), ("rangle", "⟩"), ("lfloor", "⌊"), ("rfloor", "⌋"),
+    ("lceil", "⌈"), ("rceil", "⌉"), ("mid", "∣"), ("parallel", "∥"), ("perp", "⊥"), ("colon", ":"),
+    ("vert", "|"), ("Vert", "‖"), ("lvert", "|"), ("rvert", "|"), ("lVert", "‖"), ("rVert", "‖"),
+];
+
+const LARGE_OPERATORS: &[(&str, &str)] = &[
+    ("sum", "∑"), ("prod", "∏"), ("coprod", "∐"), ("int", "∫"), ("iint", "∬"), ("iiint", "∭"), ("oint", "∮"),
+    ("bigcup", "⋃"), ("bigcap", "⋂"), ("bigoplus", "⨁"), ("bigotimes", "⨂"),
+];
+
+// function names whose scripts are placed below and above
+const LIMIT_OPERATORS: &[&str] = &["lim", "limsup", "liminf", "max", "min", "sup", "inf", "Pr", "argmax", "argmin"];
+
+const FUNCTIONS: &[&str] = &[
+    "sin", "cos", "tan", "cot", "sec", "csc", "arcsin", "arccos", "arctan", "sinh", "cosh", "tanh",
+    "log", "ln", "lg", "exp", "det", "dim", "ker", "deg", "gcd", "arg", "hom",
+];
+
+const SPACES: &[(&str, &str)] = &[
+    (",", "0.1667em"), (":", "0.2222em"), (">", "0.2222em"), (";", "0.2778em"), ("!", "-0.1667em"),
+    (" ", "0.25em"), ("quad", "1em"), ("qquad", "2em"),
+];
+
+// the accent characters, and whether they are placed over the base
+const ACCENTS: &[(&str, &str, bool)] = &[
+    ("hat", "^", true), ("widehat", "^", true), ("bar", "¯", true), ("overline", "¯", true), ("vec", "→", true),
+    ("overrightarrow", "→", true), ("dot", "˙", true), ("ddot", "¨", true), ("tilde", "~", true),
+    ("widetilde", "~", true), ("check", "ˇ", true), ("breve", "˘", true), ("acute", "´", true), ("grave", "`", true),
+    ("overbrace", "⏞", true), ("underline", "_", false), ("underbrace", "⏟", false),
+];
\ No newline at end of file
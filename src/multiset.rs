@@ -2,6 +2,12 @@ use std::collections::BTreeMap;
 
 pub struct MultiSet<T> (BTreeMap<T, usize>);
 
+impl<T: Ord> Default for MultiSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T: Ord> MultiSet<T> {
     pub fn new() -> Self {
         MultiSet (BTreeMap::new())
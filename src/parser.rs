@@ -1,81 +1,124 @@
+use std::mem;
+use std::collections::{HashMap, HashSet};
+use std::sync::LazyLock;
 use tokio;
 use regex::Regex;
 use reqwest::{self, header};
 
 use crate::data::*;
+use crate::entity::{split_entity, decode_entity, decode_entities};
+use crate::frontmatter::split_front_matter;
 use crate::multiset::MultiSet;
 use Block::*;
 use Span::*;
 
-pub fn parse_markdown(doc: &str) -> (String, List, Vec<Block>) {
+pub fn parse_markdown(doc: &str) -> (Metadata, List, Vec<Block>, Vec<Footnote>) {
+    let (mut meta, doc) = split_front_matter(doc);
     let mut parser = Parser::new(doc);
     parser.parse_markdown();
-    return (parser.title, parser.toc, parser.content);
+    resolve_refs(&mut parser.content, &parser.labels);
+
+    // footnotes are numbered in order of their first references
+    let mut footnotes = Vec::new();
+    for (i, (label, refs)) in mem::take(&mut parser.footnotes.order).into_iter().enumerate() {
+        let mut blocks = parser.footnotes.blocks.remove(&label).unwrap_or_default();
+        resolve_refs(&mut blocks, &parser.labels);
+        footnotes.push(Footnote { number: i + 1, refs, blocks });
+    }
+    if meta.title.is_empty() {
+        meta.title = parser.title;
+    }
+    (meta, parser.toc, parser.content, footnotes)
 }
 
+// the depth of blockquotes and list items, beyond which their markers are text
+const MAX_DEPTH: usize = 20;
+
 pub struct Parser<'a> {
     chs: &'a str,
+    // the number of containers around the document
+    depth: usize,
     headers: MultiSet<String>,
+    labels: HashMap<String, usize>,
+    // the destinations and titles of link reference definitions
+    links: HashMap<String, (String, Option<String>)>,
+    // the numbers of equations, figures and tables so far
+    counts: HashMap<&'static str, usize>,
+    footnotes: Footnotes,
+    // links may not contain other links
+    in_link: bool,
     title: String,
     toc: List,
     content: Vec<Block>,
+    // the offsets in the document where the blocks of the content start
+    starts: Vec<usize>,
 }
 
 impl<'a> Parser<'a> {
     fn new(doc: &'a str) -> Self {
         Parser {
             chs: doc,
+            depth: 0,
             headers: MultiSet::new(),
+            labels: HashMap::new(),
+            links: HashMap::new(),
+            footnotes: Footnotes::default(),
+            counts: HashMap::new(),
+            in_link: false,
             title: String::new(),
-            toc: List { ordered: true, items: Vec::new() },
+            toc: List { ordered: true, start: 1, marker: '.', tight: true, items: Vec::new() },
             content: Vec::new(),
+            starts: Vec::new(),
         }
     }
 
     pub fn parse_markdown(&mut self) {
+        self.collect_link_definitions();
+        let len = self.chs.len();
         while !self.chs.is_empty() {
+            let start = len - self.chs.len();
             let block = self.parse_block();
             match block {
                 Paragraph { spans } if spans.is_empty() => {},
-                _ => { self.content.push(block); },
+                _ => {
+                    self.content.push(block);
+                    self.starts.push(start);
+                },
             }
         }
     }
 
     fn parse_block(&mut self) -> Block {
-        // header
-        if self.starts_with_next("# ") {
-            return self.parse_header(1);
-        }
-        if self.starts_with_next("## ") {
-            return self.parse_header(2);
-        }
-        if self.starts_with_next("### ") {
-            return self.parse_header(3);
-        }
-        if self.starts_with_next("#### ") {
-            return self.parse_header(4);
+        // indented code block
+        if is_indented_code(self.peek_line()) {
+            return self.parse_indented_code_block();
         }
-        if self.starts_with_next("##### ") {
-            return self.parse_header(5);
+
+        // header
+        if let Some((level, text)) = split_atx_header(self.peek_line()) {
+            self.next_line();
+            return self.parse_header(text, level);
         }
-        if self.starts_with_next("###### ") {
-            return self.parse_header(6);
+
+        // thematic break
+        if is_thematic_break(self.peek_line()) {
+            self.next_line();
+            return ThematicBreak;
         }
 
         // blockquote
-        if self.chs.starts_with("> ") {
+        if self.chs.starts_with('>') && self.depth < MAX_DEPTH {
             return self.parse_blockquote();
         }
 
         // list
-        if self.chs.starts_with("+ ") || self.chs.starts_with("- ") || self.chs.starts_with("* ") || self.starts_with_num() {
-            return ListElement(self.parse_list(0));
+        if self.list_marker().is_some() {
+            return ListElement(self.parse_list());
         }
 
         // image
-        if self.starts_with_next("![](") {
-            return self.parse_image();
+        if let Some(image) = self.parse_image_block() {
+            return image;
         }
 
         // link card
@@ -89,35 +132,116 @@ impl<'a> Parser<'a> {
         }
 
         // code block
-        if self.starts_with_next("```") {
-            return self.parse_code_block();
+        if let Some((fence, indent, info)) = split_fence(self.peek_line()) {
+            self.next_line();
+            return self.parse_code_block(fence, indent, info);
+        }
+
+        // raw HTML
+        if let Some(kind) = html_block_start(self.peek_line()) {
+            return self.parse_html_block(kind);
         }
 
         // table
-        if self.chs.starts_with("|") {
+        if self.starts_table() {
             return self.parse_table();
         }
 
+        // footnote definition
+        if let Some((label, text)) = split_footnote_definition(self.peek_line()) {
+            self.next_line();
+            return self.parse_footnote_definition(label, text);
+        }
+
+        // link reference definition, which was collected beforehand
+        if split_link_definition(self.peek_line()).is_some() {
+            self.next_line();
+            return Paragraph { spans: Vec::new() };
+        }
+
         // paragraph
-        return self.parse_paragraph();
+        self.parse_paragraph()
     }
 
-    fn parse_header(&mut self, level: u32) -> Block {
-        let spans = self.parse_spans();
-        let mut header = String::new();
-        for span in &spans {
-            match span {
-                Link { text, .. } => { header.push_str(text); },
-                Emphasis { text, .. } => { header.push_str(text); },
-                Math { math } => { header.push_str(&format!("\\({}\\)", math)) },
-                Code { code } => { header.push_str(code); },
-                Text { text } => { header.push_str(text); },
-                _ => {},
+    // the lines indented by 4 columns after `[^label]: text` continue the footnote like a list item
+    fn parse_footnote_definition(&mut self, label: String, text: &str) -> Block {
+        let (doc, _) = self.collect_indented_lines(text, 4, |parser| split_footnote_definition(parser.peek_line()).is_some());
+        let blocks = self.parse_nested(&doc);
+        self.footnotes.blocks.entry(label).or_insert(blocks);
+        Paragraph { spans: Vec::new() }
+    }
+
+    // the first line and the following lines indented by the width, or lazily continuing a paragraph unless they start another item,
+    // with whether blank lines follow them
+    fn collect_indented_lines(&mut self, first: &str, width: usize, starts_item: impl Fn(&Self) -> bool) -> (String, bool) {
+        let mut doc = format!("{}\n", first);
+        let mut open = OpenBlock::default();
+        open.push(first);
+        let mut blanks = 0;
+        while !self.chs.is_empty() {
+            let line = self.peek_line();
+            if is_blank(line) {
+                self.next_line();
+                blanks += 1;
+                open.push("");
+                continue;
+            }
+
+            let indent = line.len() - line.trim_start_matches(' ').len();
+            let lazy_continuation = open.paragraph && !self.interrupts_paragraph() && !starts_item(self);
+            if indent < width && !lazy_continuation {
+                break;
+            }
+            if blanks > 0 {
+                doc.push_str(&"\n".repeat(blanks));
+                blanks = 0;
+            }
+            let line = self.next_line();
+            let line = if indent < width { line.trim_start() } else { &line[width..] };
+            doc.push_str(line);
+            doc.push('\n');
+            open.push(line);
+        }
+        (doc, blanks > 0)
+    }
+
+    // collect the link reference definitions so that links may refer to ones defined after them
+    fn collect_link_definitions(&mut self) {
+        let mut fence: Option<&str> = None;
+        let mut in_paragraph = false;
+        for line in self.chs.lines() {
+            // definitions in code blocks are not definitions
+            if let Some(open) = fence {
+                if split_fence(line).is_some_and(|(close, _, info)| close.starts_with(&open[..1]) && close.len() >= open.len() && info.is_empty()) {
+                    fence = None;
+                }
+                continue;
+            }
+            if let Some((open, _, _)) = split_fence(line) {
+                fence = Some(open);
+                continue;
+            }
+
+            // a definition cannot interrupt a paragraph
+            if let Some((label, _)) = split_footnote_definition(line).filter(|_| !in_paragraph) {
+                self.footnotes.defined.insert(label);
+                continue;
+            }
+            match split_link_definition(line) {
+                Some((label, url, title)) if !in_paragraph => {
+                    self.links.entry(label).or_insert((url, title));
+                },
+                _ => { in_paragraph = !is_blank(line) && !is_indented_code(line) && split_atx_header(line).is_none() && !is_thematic_break(line); },
             }
         }
+    }
+
+    fn parse_header(&mut self, text: &'a str, level: u32) -> Block {
+        let spans = self.parse_spans_in(text);
+        let header = plain_text(&spans);
 
         let count = self.headers.insert(header.clone());
-        let id = if count == 0 { format!("{}", &header) } else { format!("{}-{}", &header, count) };
+        let id = if count == 0 { header.clone() } else { format!("{}-{}", &header, count) };
         let href = format!("#{}", &id);
 
         // modify title or table of contents
@@ -126,71 +250,123 @@ impl<'a> Parser<'a> {
         } else {
             let mut cur = &mut self.toc;
             for _ in 2..level {
-                cur = &mut cur.items.last_mut().unwrap().list;
+                if cur.items.is_empty() {
+                    break;
+                }
+                let blocks = &mut cur.items.last_mut().unwrap().blocks;
+                if !matches!(blocks.last(), Some(ListElement(_))) {
+                    blocks.push(ListElement(List { ordered: true, start: 1, marker: '.', tight: true, items: Vec::new() }));
+                }
+                let Some(ListElement(list)) = blocks.last_mut() else { unreachable!() };
+                cur = list;
             }
             cur.items.push(ListItem {
-                spans: vec![ Link { text: header.clone(), url: href.clone() }],
-                list: List { ordered: true, items: Vec::new() },
+                checked: None,
+                blocks: vec![ Paragraph { spans: vec![ Link { text: vec![ Text { text: header.clone() } ], url: href.clone(), title: None } ] } ],
             });
         }
         Header { spans, level, id }
     }
 
     fn parse_blockquote(&mut self) -> Block {
-        let mut lines = Vec::new();
-        while self.starts_with_next("> ") {
-            lines.push(self.parse_spans());
+        let mut doc = String::new();
+        let mut open = OpenBlock::default();
+        while !self.chs.is_empty() {
+            let line = if self.starts_with_next(">") {
+                self.starts_with_next(" ");
+                self.next_line()
+            } else if open.paragraph && !is_blank(self.peek_line()) && !self.interrupts_paragraph() {
+                // lazy continuation of a paragraph in the quote
+                self.next_line()
+            } else {
+                break;
+            };
+            open.push(line);
+            doc.push_str(line);
+            doc.push('\n');
         }
-        Blockquote { lines }
+        Blockquote { blocks: self.parse_nested(&doc) }
     }
 
-    fn parse_list(&mut self, min_indent: usize) -> List {
-        let mut ordered = false;
+    fn parse_list(&mut self) -> List {
+        let (marker, start, _) = self.list_marker().unwrap();
+        let mut tight = true;
         let mut items = Vec::new();
-        while !self.chs.is_empty() {
-            let mut indent = 0;
-            let mut chs = self.chs;
-            while let Some((c, rest)) = uncons(chs) {
-                if c == ' ' {
-                    chs = rest;
-                    indent += 1;
-                } else {
-                    break;
-                }
+        // a different bullet character or delimiter starts a new list
+        while let Some((_, _, width)) = self.list_marker().filter(|&(m, _, _)| m == marker) {
+            let (item, loose, blank_after) = self.parse_list_item(width);
+            items.push(item);
+
+            // a list is loose if its items are separated by blank lines, or an item has blank lines between its blocks
+            if loose || blank_after && self.list_marker().is_some_and(|(m, _, _)| m == marker) {
+                tight = false;
             }
+        }
+        List { ordered: is_ordered(marker), start, marker, tight, items }
+    }
 
-            if min_indent <= indent {
-                self.chs = chs;
+    // the lines indented by the width of the marker belong to the item
+    fn parse_list_item(&mut self, width: usize) -> (ListItem, bool, bool) {
+        let first = self.next_line();
+        let (checked, first) = split_task_marker(first.get(width..).unwrap_or(""));
+        let (doc, blank_after) = self.collect_indented_lines(first, width, |parser| parser.list_marker().is_some());
 
-                if self.starts_with_next("+ ") || self.starts_with_next("- ") || self.starts_with_next("* ") {
-                    ordered = false;
-                    items.push(ListItem {
-                        spans: self.parse_spans(),
-                        list: self.parse_list(indent + 1),
-                    });
-                    continue;
-                }
+        // blank lines only in a block, such as a code block or a sublist, do not make the list loose
+        let (blocks, starts) = self.parse_nested_with_starts(&doc);
+        let loose = starts.iter().skip(1).any(|&start| doc[..start].strip_suffix('\n').and_then(|before| before.rsplit('\n').next()).is_some_and(is_blank));
+        (ListItem { checked, blocks }, loose, blank_after)
+    }
 
-                if self.starts_with_num_next() {
-                    ordered = true;
-                    items.push(ListItem {
-                        spans: self.parse_spans(),
-                        list: self.parse_list(indent + 1),
-                    });
-                    continue;
-                }
-            }
-            break;
+    // the bullet character or the delimiter of an ordered list item marker at the start of the line,
+    // its number, and the width up to its content
+    fn list_marker(&self) -> Option<(char, u32, usize)> {
+        let line = self.peek_line().trim_end_matches('\r');
+        let indent = line.len() - line.trim_start_matches(' ').len();
+        if indent > 3 || is_thematic_break(line) || self.depth >= MAX_DEPTH {
+            return None;
+        }
+        let chs = &line[indent..];
+
+        let digits = chs.len() - chs.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        let (marker, start, len) = if chs.starts_with(['+', '-', '*']) {
+            (chs.chars().next().unwrap(), 1, 1)
+        } else if (1..=9).contains(&digits) && chs[digits..].starts_with(['.', ')']) {
+            (chs[digits..].chars().next().unwrap(), chs[..digits].parse().unwrap(), digits + 1)
+        } else {
+            return None;
+        };
+
+        let rest = &chs[len..];
+        let spaces = rest.len() - rest.trim_start_matches(' ').len();
+        if rest.trim().is_empty() {
+            return Some((marker, start, indent + len + 1));
+        }
+        match spaces {
+            0 => None,
+            1..=4 => Some((marker, start, indent + len + spaces)),
+            // the content starts with an indented code block
+            _ => Some((marker, start, indent + len + 1)),
         }
-        List { ordered, items }
     }
 
-    fn parse_image(&mut self) -> Block {
-        let mut url = String::new();
-        while let Some(c) = self.next_char_until(")") {
-            url.push(c);
+    // an image alone in a line
+    fn parse_image_block(&mut self) -> Option<Block> {
+        if !is_image_line(self.chs) {
+            return None;
         }
-        Image { url }
+        let ((inner, url, title, attrs), rest) = split_image(self.chs)?;
+        self.chs = rest;
+        self.next_line();
+
+        // an image with alt text makes a figure captioned with it
+        let caption = self.parse_spans_in(inner);
+        let image = Block::Image { alt: plain_text(&caption), url, title, width: attribute(&attrs, "width"), height: attribute(&attrs, "height") };
+        if caption.is_empty() {
+            return Some(image);
+        }
+        let id = attribute(&attrs, "id").map(|id| label_of(&id, "fig"));
+        let number = self.next_number("fig", &id);
+        Some(Figure { id, number, caption, block: Box::new(image) })
     }
 
     fn parse_link_card(&mut self) -> Block {
@@ -205,85 +381,298 @@ impl<'a> Parser<'a> {
     fn parse_math_block(&mut self) -> Block {
         let mut math = String::new();
         while let Some(c) = self.next_char_until("$$") {
-            math.push_str(&self.escape(c));
+            math.push(c);
+        }
+
+        // a label is given by `{#eq:label}` after the block or `\label{label}` inside it
+        let mut label = None;
+        if let Some(name) = self.peek_line().trim().strip_prefix("{#").and_then(|rest| rest.strip_suffix('}')) {
+            label = Some(label_of(name, "eq"));
+            self.next_line();
         }
-        MathBlock { math }
+        if let Some((name, _)) = math.split_once("\\label{").and_then(|(_, rest)| rest.split_once('}')) {
+            let name = name.to_string();
+            math = math.replacen(&format!("\\label{{{}}}", name), "", 1);
+            label = label.or(Some(label_of(&name, "eq")));
+        }
+
+        // only labeled equations are numbered
+        let number = label.is_some().then(|| self.next_number("eq", &label));
+        MathBlock { math, label, number }
     }
 
-    fn parse_code_block(&mut self) -> Block {
-        let mut lang = String::new();
-        while let Some(c) = self.next_char_until_newline() {
-            lang.push(c);
+    // the number of the next equation, figure or table, which its label refers to
+    fn next_number(&mut self, kind: &'static str, label: &Option<String>) -> usize {
+        let count = self.counts.entry(kind).or_insert(0);
+        *count += 1;
+        if let Some(label) = label {
+            self.labels.entry(label.clone()).or_insert(*count);
+        }
+        *count
+    }
+
+    fn parse_code_block(&mut self, fence: &str, indent: usize, info: &str) -> Block {
+        let (lang, title, line_numbers, highlight) = parse_info_string(info);
+        let mut code = String::new();
+        while !self.chs.is_empty() {
+            let line = self.next_line();
+            // the closing fence is at least as long as the opening one
+            if split_fence(line).is_some_and(|(close, _, info)| close.starts_with(fence) && info.is_empty()) {
+                break;
+            }
+            let spaces = line.len() - line.trim_start_matches(' ').len();
+            code.push_str(&line[spaces.min(indent)..]);
+            code.push('\n');
         }
+        CodeBlock { lang, title, line_numbers, highlight, code }
+    }
+
+    fn parse_indented_code_block(&mut self) -> Block {
         let mut code = String::new();
-        while let Some(c) = self.next_char_until("```") {
-            code.push_str(&self.escape(c));
+        let mut blanks = 0;
+        while !self.chs.is_empty() {
+            let line = self.peek_line();
+            if is_blank(line) {
+                blanks += 1;
+            } else if is_indented_code(line) {
+                code.push_str(&"\n".repeat(blanks));
+                blanks = 0;
+                code.push_str(&strip_indent(line.trim_end_matches('\r'), 4));
+                code.push('\n');
+            } else {
+                break;
+            }
+            self.next_line();
         }
-        CodeBlock { lang, code }
+        CodeBlock { lang: String::new(), title: None, line_numbers: false, highlight: Vec::new(), code }
+    }
+
+    // the block continues up to the line with the end condition of its kind, or a blank line
+    fn parse_html_block(&mut self, kind: u32) -> Block {
+        let mut html = String::new();
+        while !self.chs.is_empty() {
+            if kind >= 6 && is_blank(self.peek_line()) {
+                break;
+            }
+            let line = self.next_line();
+            html.push_str(line);
+            html.push('\n');
+            if html_block_end(kind, line) {
+                break;
+            }
+        }
+        Block::Html { html }
     }
 
     fn parse_table(&mut self) -> Block {
+        // the rows before the delimiter row make the header
         let mut head = Vec::new();
-        let mut body = Vec::new();
-        while let Some(row) = self.parse_table_row() {
+        let mut align = Vec::new();
+        while self.is_table_row() {
+            let row = split_table_row(self.next_line());
+            if let Some(row_align) = table_alignment(&row) {
+                align = row_align;
+                break;
+            }
             head.push(row);
         }
-        while let Some(row) = self.parse_table_row() {
-            body.push(row);
+        let mut body = Vec::new();
+        while self.is_table_row() {
+            body.push(split_table_row(self.next_line()));
         }
-        Table { head, body }
+
+        // every row has as many cells as the header
+        let width = head.first().map_or(align.len(), Vec::len);
+        let head = head.into_iter().map(|row| self.parse_table_cells(row, width)).collect();
+        let body = body.into_iter().map(|row| self.parse_table_cells(row, width)).collect();
+        let table = Table { align, head, body };
+
+        // a line `Table: caption {#tbl:label}` after the table makes it numbered
+        let rest = self.chs.trim_start();
+        let Some(line) = rest.strip_prefix("Table:") else { return table };
+        self.chs = rest;
+        self.next_line();
+        let line = line.split('\n').next().unwrap_or("").trim();
+        let (text, id) = match line.rfind('{').and_then(|i| Some((&line[..i], split_attributes(&line[i..])?))) {
+            Some((text, (attrs, rest))) if rest.trim().is_empty() => (text.trim(), attribute(&attrs, "id")),
+            _ => (line, None),
+        };
+        let caption = self.parse_spans_in(text);
+        let id = id.map(|id| label_of(&id, "tbl"));
+        let number = self.next_number("tbl", &id);
+        Figure { id, number, caption, block: Box::new(table) }
     }
 
-    fn parse_table_row(&mut self) -> Option<Vec<String>> {
-        if !self.starts_with_next("|") {
-            return None;
-        }
+    fn parse_table_cells(&mut self, row: Vec<&'a str>, width: usize) -> Vec<Vec<Span>> {
+        let mut cells: Vec<Vec<Span>> = row.into_iter().take(width).map(|cell| {
+            let mut spans = self.parse_spans_in(cell);
+            unescape_pipes(&mut spans);
+            spans
+        }).collect();
+        cells.resize(width, Vec::new());
+        cells
+    }
 
-        let mut row: Vec<String> = Vec::new();
-        while !self.chs.is_empty() && !self.starts_with_newline_next() {
-            let mut data = String::new();
-            while let Some(c) = self.next_char_except("|\r\n") {
-                data.push_str(&self.escape(c));
-            }
-            row.push(data);
-            self.starts_with_next("|");
+    // a table needs a delimiter row after its header rows, which start with pipes unless there is only one
+    fn starts_table(&self) -> bool {
+        self.starts_table_with_header(usize::MAX)
+    }
+
+    fn starts_table_with_header(&self, max_rows: usize) -> bool {
+        if !self.chs.starts_with('|') {
+            return self.starts_table_without_pipes();
         }
-        if row.iter().all(|s| s.chars().all(|c| c == '-' || c == ' ')) {
-            return None;
+        for (i, line) in self.chs.split('\n').take(max_rows.saturating_add(1)).enumerate() {
+            let line = line.trim_end_matches('\r');
+            if !line.starts_with('|') {
+                return false;
+            }
+            if table_alignment(&split_table_row(line)).is_some() {
+                return i > 0;
+            }
         }
-        Some(row)
+        false
+    }
+
+    // a table without leading pipes needs a delimiter row with as many cells as the header
+    fn starts_table_without_pipes(&self) -> bool {
+        let mut lines = self.chs.split('\n');
+        let head = lines.next().unwrap_or("");
+        let Some(delimiter) = lines.next() else { return false };
+        let head = split_table_row(head.trim_end_matches('\r'));
+        let delimiter = split_table_row(delimiter.trim_end_matches('\r'));
+        head.len() >= 2 && head.len() == delimiter.len() && table_alignment(&delimiter).is_some_and(|align| align.len() == head.len())
+            && delimiter.iter().all(|cell| !cell.is_empty())
+    }
+
+    fn is_table_row(&self) -> bool {
+        let line = self.peek_line();
+        line.starts_with('|') || !is_blank(line) && split_table_row(line.trim_end_matches('\r')).len() >= 2 && !self.interrupts_paragraph()
     }
 
     fn parse_paragraph(&mut self) -> Block {
-        Paragraph { spans: self.parse_spans() }
+        let start = self.chs;
+        if is_blank(self.next_line()) {
+            return Paragraph { spans: Vec::new() };
+        }
+
+        // consecutive lines are joined until a blank line or the start of another block
+        while !self.chs.is_empty() && !is_blank(self.peek_line()) {
+            let text = &start[..start.len() - self.chs.len()];
+            if let Some(level) = setext_level(self.peek_line()) {
+                self.next_line();
+                return self.parse_header(text.trim(), level);
+            }
+            if self.interrupts_paragraph() {
+                break;
+            }
+            self.next_line();
+        }
+        let text = &start[..start.len() - self.chs.len()];
+        Paragraph { spans: self.parse_spans_in(text.trim()) }
+    }
+
+    fn interrupts_paragraph(&self) -> bool {
+        let chs = self.chs;
+        split_atx_header(self.peek_line()).is_some()
+            || is_thematic_break(self.peek_line())
+            || chs.starts_with('>') && self.depth < MAX_DEPTH
+            || self.list_marker().is_some_and(|(marker, start, width)| {
+                // only non-empty items, and ordered lists starting with 1, can interrupt a paragraph
+                (start == 1 || !is_ordered(marker)) && !is_blank(self.peek_line().get(width..).unwrap_or(""))
+            })
+            || is_image_line(chs) || chs.starts_with("?[](")
+            || chs.starts_with("$$") || split_fence(self.peek_line()).is_some()
+            // only a table with one header row, so that a long run of rows with pipes is not scanned for every line
+            || chs.starts_with('|') && self.starts_table_with_header(1)
+            || html_block_start(self.peek_line()).is_some_and(|kind| kind != 7)
     }
 
     fn parse_spans(&mut self) -> Vec<Span> {
+        let start = self.chs;
         let mut spans = Vec::new();
-        while !self.chs.is_empty() && !self.starts_with_newline_next() {
-            // link
-            if self.starts_with_next("[") {
-                spans.push(self.parse_link());
+        let mut delims = Vec::new();
+        while !self.chs.is_empty() {
+            // line break
+            if self.starts_with_newline_next() {
+                let line_break = self.parse_line_break(&mut spans);
+                spans.push(line_break);
+                continue;
+            }
+
+            // reference to an equation, a figure or a table
+            if let Some((label, rest)) = split_ref(self.chs) {
+                self.chs = rest;
+                spans.push(Ref { label: label_of(label, "eq"), number: None });
+                continue;
+            }
+
+            // backslash escape
+            if self.starts_with_next("\\") {
+                spans.push(self.parse_escape());
+                continue;
+            }
+
+            // raw HTML
+            if let Some((html, rest)) = split_raw_html(self.chs) {
+                self.chs = rest;
+                spans.push(Span::Html { html: html.to_string() });
+                continue;
+            }
+
+            // autolink
+            if self.chs.starts_with('<') {
+                spans.push(self.parse_autolink());
+                continue;
+            }
+
+            // bare URL at the start of a word
+            let prev = start[..start.len() - self.chs.len()].chars().next_back();
+            if !self.in_link && !prev.is_some_and(char::is_alphanumeric) {
+                if let Some(len) = url_len(self.chs) {
+                    let (url, rest) = self.chs.split_at(len);
+                    self.chs = rest;
+                    let href = if url.starts_with("www.") { format!("http://{}", url) } else { url.to_string() };
+                    spans.push(Link { text: vec![ Text { text: url.to_string() } ], url: href, title: None });
+                    continue;
+                }
+            }
+
+            // entity reference
+            if self.chs.starts_with('&') {
+                spans.push(self.parse_entity());
                 continue;
             }
 
-            // strong
-            if self.starts_with_next("**") {
-                spans.push(self.parse_strong('*'));
+            // image
+            if self.chs.starts_with("![") {
+                match self.parse_image() {
+                    Some(image) => { spans.push(image); },
+                    None => {
+                        self.chs = &self.chs[1..];
+                        spans.push(Text { text: String::from("!") });
+                    },
+                }
                 continue;
             }
-            if self.starts_with_next("__") {
-                spans.push(self.parse_strong('_'));
+
+            // footnote reference
+            if let Some(footnote_ref) = self.parse_footnote_ref() {
+                spans.push(footnote_ref);
                 continue;
             }
 
-            // emphasis
-            if self.starts_with_next("*") {
-                spans.push(self.parse_emphasis('*'));
+            // link
+            if self.starts_with_next("[") {
+                spans.push(self.parse_link());
                 continue;
             }
-            if self.starts_with_next("_") {
-                spans.push(self.parse_emphasis('_'));
+
+            // emphasis, strong and strikethrough
+            if self.chs.starts_with(['*', '_', '~']) {
+                let prev = start[..start.len() - self.chs.len()].chars().next_back();
+                delims.push(self.parse_delimiter_run(prev, spans.len()));
+                spans.push(Text { text: delims.last().unwrap().run() });
                 continue;
             }
 
@@ -294,7 +683,7 @@ impl<'a> Parser<'a> {
             }
 
             // code
-            if self.starts_with_next("`") {
+            if self.chs.starts_with('`') {
                 spans.push(self.parse_code());
                 continue;
             }
@@ -302,136 +691,265 @@ impl<'a> Parser<'a> {
             // text
             spans.push(self.parse_text());
         }
+        process_emphasis(&mut spans, &mut delims);
+        spans.retain(|span| !is_empty_text(span));
         spans
     }
 
     fn parse_link(&mut self) -> Span {
-        let mut text = String::new();
-        let mut url = String::new();
-        let mut chs = self.chs;
-
-        loop {
-            match uncons_except_newline(chs) {
-                Some((']', rest)) => { chs = rest; break; },
-                Some((c, rest)) => { chs = rest; text.push_str(&self.escape(c)); },
-                None => { return Text { text: String::from("[") }; },
-            }
+        // the brackets of another link in link text are literal
+        if self.in_link {
+            return Text { text: String::from("[") };
         }
+        let Some((inner, rest)) = split_link_text(self.chs) else {
+            return Text { text: String::from("[") };
+        };
 
-        match uncons_except_newline(chs) {
-            Some(('(', rest)) => { chs = rest; },
-            _ => { return Text { text: String::from("[") }; },
-        }
+        let (url, title, rest) = if let Some((url, title, rest)) = rest.strip_prefix('(').and_then(split_destination) {
+            (url, title, rest)
+        } else {
+            // full `[text][label]`, collapsed `[label][]` and shortcut `[label]` reference links
+            let (label, rest) = match rest.strip_prefix('[').and_then(split_link_text) {
+                Some(("", rest)) => (inner, rest),
+                Some((label, rest)) => (label, rest),
+                None => (inner, rest),
+            };
+            let Some((url, title)) = self.links.get(&normalize_label(label)).cloned() else {
+                return Text { text: String::from("[") };
+            };
+            (url, title, rest)
+        };
+        self.chs = rest;
+
+        let text = if inner.is_empty() {
+            vec![ Text { text: get_title(&url) } ]
+        } else {
+            let in_link = mem::replace(&mut self.in_link, true);
+            let text = self.parse_spans_in(inner);
+            self.in_link = in_link;
+            text
+        };
 
-        loop {
-            match uncons_except_newline(chs) {
-                Some((')', rest)) => { chs = rest; break; },
-                Some((c, rest)) => { chs = rest; url.push(c); },
-                None => { return Text { text: String::from("[") }; },
-            }
+        Link { text, url, title }
+    }
+
+    fn parse_footnote_ref(&mut self) -> Option<Span> {
+        let (label, rest) = split_link_text(self.chs.strip_prefix("[^")?)?;
+        let label = normalize_label(label);
+        if !self.footnotes.defined.contains(&label) {
+            return None;
         }
+        self.chs = rest;
+
+        let order = &mut self.footnotes.order;
+        let number = match order.iter().position(|(l, _)| *l == label) {
+            Some(i) => i + 1,
+            None => {
+                order.push((label, 0));
+                order.len()
+            },
+        };
+        order[number - 1].1 += 1;
+        Some(FootnoteRef { number, index: order[number - 1].1 })
+    }
 
-        self.chs = chs;
+    fn parse_image(&mut self) -> Option<Span> {
+        let ((inner, url, title, attrs), rest) = split_image(self.chs)?;
+        self.chs = rest;
+        // the alt text is the plain text of the description
+        let alt = plain_text(&self.parse_spans_in(inner));
+        Some(Span::Image { alt, url, title, width: attribute(&attrs, "width"), height: attribute(&attrs, "height") })
+    }
 
-        if text.is_empty() {
-            text = get_title(&url);
-        }
+    fn parse_delimiter_run(&mut self, prev: Option<char>, index: usize) -> Delimiter {
+        let ch = self.chs.chars().next().unwrap();
+        let rest = self.chs.trim_start_matches(ch);
+        let len = self.chs.len() - rest.len();
+        self.chs = rest;
+        let next = self.chs.chars().next();
+
+        // a run is left-flanking if it is not followed by whitespace, and either not followed by punctuation or preceded by whitespace or punctuation
+        let left_flanking = !is_whitespace(next) && (!is_punctuation(next) || is_whitespace(prev) || is_punctuation(prev));
+        let right_flanking = !is_whitespace(prev) && (!is_punctuation(prev) || is_whitespace(next) || is_punctuation(next));
+        let (can_open, can_close) = if ch == '*' {
+            (left_flanking, right_flanking)
+        } else if ch == '~' {
+            // only `~` and `~~` strike through text
+            (left_flanking && len <= 2, right_flanking && len <= 2)
+        } else {
+            // `_` may not open or close intraword
+            (left_flanking && (!right_flanking || is_punctuation(prev)), right_flanking && (!left_flanking || is_punctuation(next)))
+        };
 
-        Link { text, url }
+        Delimiter { index, ch, len, orig_len: len, can_open, can_close }
     }
 
-    fn parse_strong(&mut self, d: char) -> Span {
-        let mut text = String::new();
+    fn parse_math(&mut self) -> Span {
+        // as in pandoc, `$` opens math only before a non-space, and closes it only after a non-space and before a non-digit,
+        // so that prices such as `$5 and $10` stay text
+        if self.chs.starts_with(char::is_whitespace) {
+            return Text { text: String::from("$") };
+        }
+
+        let mut math = String::new();
         let mut chs = self.chs;
+        let mut prev = None;
         while let Some((c, rest)) = uncons_except_newline(chs) {
-            if c == d {
+            if c == '$' && !is_whitespace(prev) && !rest.starts_with(|c: char| c.is_ascii_digit()) {
                 self.chs = rest;
-                if self.starts_with_next(&d.to_string()) {
-                    return Strong { text };
-                } else {
-                    return Emphasis { text };
-                }
+                return Math { math };
             }
             chs = rest;
-            text.push_str(&self.escape(c));
+            math.push(c);
+            if c == '\\' {
+                if let Some((c, rest)) = uncons_except_newline(chs) {
+                    chs = rest;
+                    math.push(c);
+                }
+            }
+            prev = Some(c);
         }
-        Text { text: format!("{0}{0}", d) }
+        Text { text: String::from("$") }
     }
 
-    fn parse_emphasis(&mut self, d: char) -> Span {
-        let mut text = String::new();
-        let mut chs = self.chs;
-        while let Some((c, rest)) = uncons_except_newline(chs) {
-            if c == d {
+    fn parse_line_break(&mut self, spans: &mut [Span]) -> Span {
+        // two or more trailing spaces make a hard break
+        let mut hard = false;
+        if let Some(Text { text }) = spans.last_mut() {
+            let len = text.trim_end_matches(' ').len();
+            hard = text.len() - len >= 2;
+            text.truncate(len);
+        }
+        self.chs = self.chs.trim_start_matches([' ', '\t']);
+        if hard { HardBreak } else { SoftBreak }
+    }
+
+    fn parse_escape(&mut self) -> Span {
+        // a backslash at the end of a line makes a hard break
+        if self.starts_with_newline_next() {
+            self.chs = self.chs.trim_start_matches([' ', '\t']);
+            return HardBreak;
+        }
+        match uncons(self.chs) {
+            Some((c, rest)) if is_escapable(c) => {
                 self.chs = rest;
-                return Emphasis { text };
-            }
-            chs = rest;
-            text.push_str(&self.escape(c));
+                Text { text: c.to_string() }
+            },
+            _ => Text { text: String::from("\\") },
         }
-        Text { text: d.to_string() }
     }
 
-    fn parse_math(&mut self) -> Span {
-        let mut math = String::new();
-        let mut chs = self.chs;
-        while let Some((c, rest)) = uncons_except_newline(chs) {
-            if c == '$' {
+    fn parse_entity(&mut self) -> Span {
+        if let Some((name, rest)) = split_entity(self.chs) {
+            if let Some(c) = decode_entity(name) {
                 self.chs = rest;
-                return Math { math };
+                return Text { text: c.to_string() };
             }
-            chs = rest;
-            math.push_str(&self.escape(c));
         }
-        Text { text: String::from("$") }
+        self.chs = &self.chs[1..];
+        Text { text: String::from("&") }
     }
 
     fn parse_code(&mut self) -> Span {
-        let mut code = String::new();
-        let mut chs = self.chs;
-        while let Some((c, rest)) = uncons_except_newline(chs) {
-            if c == '`' {
-                self.chs = rest;
-                return Code { code };
+        let Some((code, rest)) = split_code_span(self.chs) else {
+            // a backtick string without the closing one is literal
+            let len = self.chs.len() - self.chs.trim_start_matches('`').len();
+            let (ticks, rest) = self.chs.split_at(len);
+            self.chs = rest;
+            return Text { text: ticks.to_string() };
+        };
+        self.chs = rest;
+
+        // line endings in code spans are treated as spaces
+        let code: String = code.chars().filter(|&c| c != '\r').map(|c| if c == '\n' { ' ' } else { c }).collect();
+        // a space on both sides is stripped so that code can start or end with a backtick
+        let code = match code.strip_prefix(' ').and_then(|code| code.strip_suffix(' ')) {
+            Some(inner) if !inner.trim_start_matches(' ').is_empty() => inner.to_string(),
+            _ => code,
+        };
+        Code { code }
+    }
+
+    fn parse_autolink(&mut self) -> Span {
+        self.chs = &self.chs[1..];
+        let end = self.chs.find(|c: char| c == '>' || c == '<' || c.is_whitespace() || c.is_control());
+        if let Some(end) = end.filter(|&end| !self.in_link && self.chs[end..].starts_with('>')) {
+            let inner = &self.chs[..end];
+            let url = if is_absolute_uri(inner) {
+                Some(inner.to_string())
+            } else if is_email(inner) {
+                Some(format!("mailto:{}", inner))
+            } else {
+                None
+            };
+            if let Some(url) = url {
+                self.chs = &self.chs[end + 1..];
+                return Link { text: vec![ Text { text: inner.to_string() } ], url, title: None };
             }
-            chs = rest;
-            code.push_str(&self.escape(c));
         }
-        Text { text: String::from("`") }
+        Text { text: String::from("<") }
     }
 
     fn parse_text(&mut self) -> Span {
         let mut text = String::new();
-        while let Some(c) = self.next_char_except("[*_$`\r\n") {
-            text.push_str(&self.escape(c));
+        while let Some(c) = self.next_char_except("\\&[*_~$`<\r\n") {
+            text.push(c);
+            // stop before a bare URL or an image
+            if !self.in_link && !c.is_alphanumeric() && url_len(self.chs).is_some() || self.chs.starts_with("![") {
+                break;
+            }
         }
         Text { text }
     }
 
+    // parse a document built from the lines of a container block, sharing the header ids with this one
+    fn parse_nested(&mut self, doc: &str) -> Vec<Block> {
+        self.parse_nested_with_starts(doc).0
+    }
+
+    fn parse_nested_with_starts(&mut self, doc: &str) -> (Vec<Block>, Vec<usize>) {
+        let mut parser = Parser::new(doc);
+        parser.depth = self.depth + 1;
+        mem::swap(&mut parser.headers, &mut self.headers);
+        mem::swap(&mut parser.labels, &mut self.labels);
+        mem::swap(&mut parser.counts, &mut self.counts);
+        mem::swap(&mut parser.links, &mut self.links);
+        mem::swap(&mut parser.footnotes, &mut self.footnotes);
+        parser.parse_markdown();
+        mem::swap(&mut parser.headers, &mut self.headers);
+        mem::swap(&mut parser.labels, &mut self.labels);
+        mem::swap(&mut parser.counts, &mut self.counts);
+        mem::swap(&mut parser.links, &mut self.links);
+        mem::swap(&mut parser.footnotes, &mut self.footnotes);
+        (parser.content, parser.starts)
+    }
+
+    fn parse_spans_in(&mut self, text: &'a str) -> Vec<Span> {
+        let rest = self.chs;
+        self.chs = text;
+        let spans = self.parse_spans();
+        self.chs = rest;
+        spans
+    }
+
+    fn next_line(&mut self) -> &'a str {
+        let line = self.peek_line();
+        self.chs = &self.chs[line.len()..];
+        self.starts_with_newline_next();
+        line.strip_suffix('\r').unwrap_or(line)
+    }
+
+    fn peek_line(&self) -> &'a str {
+        self.chs.split('\n').next().unwrap_or("")
+    }
+
     fn next_char_until(&mut self, until: &str) -> Option<char> {
         if self.chs.starts_with(until) {
             let len = until.chars().count();
             self.chs = &self.chs[len..];
             return None;
         }
-        if let Some(c) = self.chs.chars().nth(0) {
-            let i = if let Some((i, _)) = self.chs.char_indices().nth(1) { i } else { self.chs.len() };
-            self.chs = &self.chs[i..];
-            return Some(c);
-        }
-        None
-    }
-
-    fn next_char_until_newline(&mut self) -> Option<char> {
-        if self.chs.starts_with("\n") {
-            self.chs = &self.chs[1..];
-            return None;
-        }
-        if self.chs.starts_with("\r\n") {
-            self.chs = &self.chs[2..];
-            return None;
-        }
-        if let Some(c) = self.chs.chars().nth(0) {
+        if let Some(c) = self.chs.chars().next() {
             let i = if let Some((i, _)) = self.chs.char_indices().nth(1) { i } else { self.chs.len() };
             self.chs = &self.chs[i..];
             return Some(c);
@@ -440,7 +958,7 @@ impl<'a> Parser<'a> {
     }
 
     fn next_char_except(&mut self, except: &str) -> Option<char> {
-        if let Some(c) = self.chs.chars().nth(0) {
+        if let Some(c) = self.chs.chars().next() {
             if !except.contains(c) {
                 let i = if let Some((i, _)) = self.chs.char_indices().nth(1) { i } else { self.chs.len() };
                 self.chs = &self.chs[i..];
@@ -450,21 +968,6 @@ impl<'a> Parser<'a> {
         None
     }
 
-    fn starts_with_num(&self) -> bool {
-        let chs = self.chs.trim_start_matches(|c: char| c.is_ascii_digit());
-        chs.strip_prefix(". ").is_some()
-    }
-
-    fn starts_with_num_next(&mut self) -> bool {
-        let chs = self.chs.trim_start_matches(|c: char| c.is_ascii_digit());
-        if let Some(chs) = chs.strip_prefix(". ") {
-            self.chs = chs;
-            true
-        } else {
-            false
-        }
-    }
-
     fn starts_with_next(&mut self, prefix: &str) -> bool {
         if let Some(chs) = self.chs.strip_prefix(prefix) {
             self.chs = chs;
@@ -485,18 +988,810 @@ impl<'a> Parser<'a> {
             false
         }
     }
+}
+
+#[derive(Default)]
+struct Footnotes {
+    // the labels of the definitions, collected beforehand
+    defined: HashSet<String>,
+    // the labels in order of their first references, with the numbers of references
+    order: Vec<(String, usize)>,
+    blocks: HashMap<String, Vec<Block>>,
+}
+
+// the last block of the lines collected for a container, since lazy continuation lines may only continue a paragraph
+#[derive(Default)]
+struct OpenBlock {
+    // the opening fence of a code block not closed yet
+    fence: Option<String>,
+    paragraph: bool,
+}
+
+impl OpenBlock {
+    fn push(&mut self, line: &str) {
+        if let Some(open) = &self.fence {
+            if split_fence(line).is_some_and(|(close, _, info)| close.starts_with(open.as_str()) && info.is_empty()) {
+                self.fence = None;
+            }
+            return;
+        }
+        if let Some((fence, _, _)) = split_fence(line) {
+            self.fence = Some(fence.to_string());
+            self.paragraph = false;
+            return;
+        }
+        self.paragraph = !is_blank(line) && (self.paragraph && !Parser::new(line).interrupts_paragraph() || starts_paragraph(line));
+    }
+}
+
+struct Delimiter {
+    index: usize,
+    ch: char,
+    len: usize,
+    orig_len: usize,
+    can_open: bool,
+    can_close: bool,
+}
+
+impl Delimiter {
+    fn run(&self) -> String {
+        self.ch.to_string().repeat(self.len)
+    }
+}
+
+// match closers with the nearest possible openers, and wrap the spans between them
+fn process_emphasis(spans: &mut [Span], delims: &mut [Delimiter]) {
+    // the delimiters are linked to their neighbours so that removing some keeps the indices of the others
+    let len = delims.len();
+    let mut prev: Vec<Option<usize>> = (0..len).map(|i| i.checked_sub(1)).collect();
+    let mut next: Vec<Option<usize>> = (1..=len).map(|i| Some(i).filter(|&i| i < len)).collect();
+    // the delimiters below which no opener was found, by the character, whether the closer can open and its length
+    let mut openers_bottom: HashMap<(char, bool, usize), Option<usize>> = HashMap::new();
+
+    let mut closer = Some(0).filter(|_| len > 0);
+    while let Some(c) = closer {
+        if !delims[c].can_close {
+            closer = next[c];
+            continue;
+        }
+
+        let key = (delims[c].ch, delims[c].can_open, if delims[c].ch == '~' { delims[c].len } else { delims[c].orig_len % 3 });
+        let bottom = openers_bottom.get(&key).copied().flatten();
+        let mut opener = None;
+        let mut cur = prev[c];
+        while let Some(o) = cur.filter(|&o| bottom.is_none_or(|bottom| o > bottom)) {
+            let (od, cd) = (&delims[o], &delims[c]);
+            // the rule of 3
+            let multiple_of_3 = (od.can_close || cd.can_open) && (od.orig_len + cd.orig_len) % 3 == 0 && (od.orig_len % 3 != 0 || cd.orig_len % 3 != 0);
+            // strikethrough needs the runs of the same length
+            if od.ch == cd.ch && od.can_open && if cd.ch == '~' { od.len == cd.len } else { !multiple_of_3 } {
+                opener = Some(o);
+                break;
+            }
+            cur = prev[o];
+        }
+        let Some(o) = opener else {
+            openers_bottom.insert(key, prev[c]);
+            if !delims[c].can_open {
+                unlink(&mut prev, &mut next, c);
+            }
+            closer = next[c];
+            continue;
+        };
+
+        // the spans between them are moved into the new span, leaving empty text removed later
+        let ch = delims[c].ch;
+        let n = if ch == '~' { delims[c].len } else if delims[o].len >= 2 && delims[c].len >= 2 { 2 } else { 1 };
+        let (begin, end) = (delims[o].index, delims[c].index);
+        let children: Vec<Span> = spans[begin + 1..end].iter_mut()
+            .map(|span| mem::replace(span, Text { text: String::new() }))
+            .filter(|span| !is_empty_text(span))
+            .collect();
+        spans[begin + 1] = match (ch, n) {
+            ('~', _) => Strikethrough { spans: children },
+            (_, 2) => Strong { spans: children },
+            _ => Emphasis { spans: children },
+        };
+
+        // delimiters between the opener and the closer are now inside the new span
+        next[o] = Some(c);
+        prev[c] = Some(o);
+
+        for i in [o, c] {
+            delims[i].len -= n;
+            spans[delims[i].index] = Text { text: delims[i].run() };
+        }
+        if delims[o].len == 0 {
+            unlink(&mut prev, &mut next, o);
+        }
+        if delims[c].len == 0 {
+            unlink(&mut prev, &mut next, c);
+            closer = next[c];
+        }
+    }
+}
+
+fn unlink(prev: &mut [Option<usize>], next: &mut [Option<usize>], i: usize) {
+    if let Some(p) = prev[i] {
+        next[p] = next[i];
+    }
+    if let Some(n) = next[i] {
+        prev[n] = prev[i];
+    }
+}
+
+// replace backslash escapes and entity references with the characters they stand for
+fn unescape(text: &str) -> String {
+    let mut res = String::new();
+    let mut chs = text;
+    while let Some((c, rest)) = uncons(chs) {
+        if c == '\\' && rest.starts_with(is_escapable) {
+            res.push_str(&rest[..1]);
+            chs = &rest[1..];
+            continue;
+        }
+        if let Some((c, rest)) = split_entity(chs).and_then(|(name, rest)| Some((decode_entity(name)?, rest))) {
+            res.push(c);
+            chs = rest;
+            continue;
+        }
+        res.push(c);
+        chs = rest;
+    }
+    res
+}
+
+fn is_escapable(c: char) -> bool {
+    c.is_ascii_punctuation()
+}
+
+// the level and the text of a header line such as `## text ##`
+fn split_atx_header(line: &str) -> Option<(u32, &str)> {
+    let chs = line.trim_start_matches(' ');
+    if line.len() - chs.len() > 3 {
+        return None;
+    }
+    let text = chs.trim_start_matches('#');
+    let level = chs.len() - text.len();
+    if !(1..=6).contains(&level) || !(text.is_empty() || text.starts_with([' ', '\t', '\r'])) {
+        return None;
+    }
+
+    // the closing sequence of `#` is dropped if it is preceded by a space
+    let text = text.trim();
+    let rest = text.trim_end_matches('#');
+    let text = if rest.is_empty() || rest.ends_with([' ', '\t']) { rest.trim_end() } else { text };
+    Some((level as u32, text))
+}
+
+// the level of the header underlined by `===` or `---`
+fn setext_level(line: &str) -> Option<u32> {
+    let chs = line.trim_start_matches(' ');
+    if line.len() - chs.len() > 3 {
+        return None;
+    }
+    let chs = chs.trim_end();
+    if !chs.is_empty() && chs.chars().all(|c| c == '=') {
+        Some(1)
+    } else if !chs.is_empty() && chs.chars().all(|c| c == '-') {
+        Some(2)
+    } else {
+        None
+    }
+}
 
-    fn escape(&self, c: char) -> String {
+// a line of four spaces or a tab of indentation
+fn is_indented_code(line: &str) -> bool {
+    (line.starts_with("    ") || line.starts_with('\t')) && !is_blank(line)
+}
+
+fn strip_indent(line: &str, width: usize) -> String {
+    let mut col = 0;
+    for (i, c) in line.char_indices() {
+        if col >= width {
+            return line[i..].to_string();
+        }
         match c {
-            '<' => String::from("&lt;"),
-            '>' => String::from("&gt;"),
-            _ => c.to_string(),
+            ' ' => { col += 1; },
+            '\t' => {
+                // a tab advances to the next multiple of 4 columns
+                let next = (col / 4 + 1) * 4;
+                if next > width {
+                    return format!("{}{}", " ".repeat(next - width), &line[i + 1..]);
+                }
+                col = next;
+            },
+            _ => { return line[i..].to_string(); },
+        }
+    }
+    String::new()
+}
+
+// the fence, its indentation and the info string of a line opening or closing a fenced code block
+fn split_fence(line: &str) -> Option<(&str, usize, &str)> {
+    let chs = line.trim_start_matches(' ');
+    let indent = line.len() - chs.len();
+    if indent > 3 {
+        return None;
+    }
+    let c = chs.chars().next().filter(|&c| c == '`' || c == '~')?;
+    let info = chs.trim_start_matches(c);
+    let fence = &chs[..chs.len() - info.len()];
+    // the info string of a backtick fence cannot contain backticks
+    if fence.len() < 3 || c == '`' && info.contains('`') {
+        return None;
+    }
+    Some((fence, indent, info.trim()))
+}
+
+// split an info string such as `rust {3,5-7} title="main.rs" linenos` into
+// the language, the title, whether to show line numbers, and the ranges of highlighted lines
+fn parse_info_string(info: &str) -> (String, Option<String>, bool, Vec<(usize, usize)>) {
+    let mut lang = String::new();
+    let mut title = None;
+    let mut line_numbers = false;
+    let mut highlight = Vec::new();
+
+    let mut chs = info.trim();
+    let mut first = true;
+    while !chs.is_empty() {
+        // a word, in which quoted values and braces may contain spaces
+        let mut end = 0;
+        let mut close = None;
+        for (i, c) in chs.char_indices() {
+            end = i + c.len_utf8();
+            match (c, close) {
+                (_, Some(d)) if c == d => { close = None; },
+                (_, Some(_)) => {},
+                ('"', None) => { close = Some('"'); },
+                ('{', None) => { close = Some('}'); },
+                (' ' | '\t', None) => { end = i; break; },
+                _ => {},
+            }
+        }
+        let word = &chs[..end];
+        chs = chs[end..].trim_start();
+
+        if let Some(ranges) = word.strip_prefix('{').and_then(|word| word.strip_suffix('}')) {
+            for range in ranges.split(',') {
+                let (begin, end) = range.split_once('-').unwrap_or((range, range));
+                if let (Ok(begin), Ok(end)) = (begin.trim().parse(), end.trim().parse()) {
+                    highlight.push((begin, end));
+                }
+            }
+        } else if let Some((key, value)) = word.split_once('=') {
+            let value = value.strip_prefix('"').and_then(|value| value.strip_suffix('"')).unwrap_or(value);
+            if key == "title" || key == "filename" {
+                title = Some(unescape(value));
+            }
+        } else if word == "linenos" || word == "showLineNumbers" {
+            line_numbers = true;
+        } else if first {
+            lang = unescape(word);
+        }
+        first = false;
+    }
+
+    (lang, title, line_numbers, highlight)
+}
+
+// the pairs of keys and values in `{key=value ...}`
+type Attributes<'a> = Vec<(&'a str, &'a str)>;
+
+// the description, the destination, the title and the attributes of an image
+type ImageParts<'a> = (&'a str, String, Option<String>, Attributes<'a>);
+
+// split `![alt](url "title"){key=value ...}` into the parts of the image and the rest
+fn split_image(chs: &str) -> Option<(ImageParts<'_>, &str)> {
+    let (inner, rest) = split_link_text(chs.strip_prefix("![")?)?;
+    let (url, title, rest) = split_destination(rest.strip_prefix('(')?)?;
+    let (attrs, rest) = split_attributes(rest).unwrap_or((Vec::new(), rest));
+    Some(((inner, url, title, attrs), rest))
+}
+
+fn is_image_line(chs: &str) -> bool {
+    split_image(chs).is_some_and(|(_, rest)| is_blank(rest.split('\n').next().unwrap_or("")))
+}
+
+// split the text in brackets, whose opening one is already consumed, and the rest
+fn split_link_text(chs: &str) -> Option<(&str, &str)> {
+    let mut rest = chs;
+    let mut depth = 0;
+    loop {
+        match uncons(rest)? {
+            (']', r) if depth == 0 => { return Some((&chs[..chs.len() - rest.len()], r)); },
+            ('\\', r) => { rest = r.strip_prefix(is_escapable).unwrap_or(r); },
+            (c, r) => {
+                rest = r;
+                if c == '[' { depth += 1; }
+                if c == ']' { depth -= 1; }
+            },
+        }
+    }
+}
+
+// split `url "title")`, whose opening parenthesis is already consumed, into the destination, the title and the rest
+fn split_destination(chs: &str) -> Option<(String, Option<String>, &str)> {
+    let (url, title, rest) = split_destination_title(chs)?;
+    Some((url, title, rest.trim_start().strip_prefix(')')?))
+}
+
+// split `[label]: url "title"` into the normalized label, the destination and the title
+fn split_link_definition(line: &str) -> Option<(String, String, Option<String>)> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 {
+        return None;
+    }
+    let (label, rest) = split_link_text(line[indent..].strip_prefix('[')?)?;
+    let rest = rest.strip_prefix(':')?;
+    if is_blank(label) || label.starts_with(['^', '@']) || is_blank(rest) {
+        return None;
+    }
+    let (url, title, rest) = split_destination_title(rest)?;
+    if !is_blank(rest) {
+        return None;
+    }
+    Some((normalize_label(label), url, title))
+}
+
+// split `[^label]: text` into the normalized label and the text
+fn split_footnote_definition(line: &str) -> Option<(String, &str)> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 {
+        return None;
+    }
+    let (label, rest) = split_link_text(line[indent..].strip_prefix("[^")?)?;
+    let text = rest.strip_prefix(':')?;
+    if is_blank(label) || label.contains(char::is_whitespace) {
+        return None;
+    }
+    Some((normalize_label(label), text.trim()))
+}
+
+// labels are matched case-insensitively with consecutive whitespace collapsed
+fn normalize_label(label: &str) -> String {
+    label.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+fn split_destination_title(chs: &str) -> Option<(String, Option<String>, &str)> {
+    let chs = chs.trim_start();
+    let (url, rest) = if let Some(rest) = chs.strip_prefix('<') {
+        let end = rest.find(['>', '<', '\n'])?;
+        (&rest[..end], rest[end..].strip_prefix('>')?)
+    } else {
+        // parentheses in the destination must be balanced
+        let mut depth = 0;
+        let mut end = chs.len();
+        let mut iter = chs.char_indices();
+        while let Some((i, c)) = iter.next() {
+            match c {
+                '\\' => { iter.next(); },
+                '(' => { depth += 1; },
+                ')' if depth == 0 => { end = i; break; },
+                ')' => { depth -= 1; },
+                c if c.is_whitespace() || c.is_control() => { end = i; break; },
+                _ => {},
+            }
+        }
+        chs.split_at(end)
+    };
+
+    let rest = rest.trim_start();
+    let (title, rest) = match rest.chars().next() {
+        Some(open @ ('"' | '\'' | '(')) => {
+            let close = if open == '(' { ')' } else { open };
+            let body = &rest[1..];
+            let mut iter = body.char_indices();
+            let mut end = None;
+            while let Some((i, c)) = iter.next() {
+                if c == '\\' {
+                    iter.next();
+                } else if c == close {
+                    end = Some(i);
+                    break;
+                }
+            }
+            let end = end?;
+            (Some(unescape(&body[..end])), &body[end + 1..])
+        },
+        _ => (None, rest),
+    };
+    Some((unescape(url), title, rest))
+}
+
+// split `{#id key=value ...}` into the attributes and the rest, where `#id` is the attribute `id`
+fn split_attributes(chs: &str) -> Option<(Attributes<'_>, &str)> {
+    let rest = chs.strip_prefix('{')?;
+    let end = rest.find(['}', '\n'])?;
+    let mut attrs = Vec::new();
+    for attr in rest[..end].split_whitespace() {
+        if let Some(id) = attr.strip_prefix('#') {
+            attrs.push(("id", id));
+        } else {
+            let (key, value) = attr.split_once('=')?;
+            attrs.push((key, value.trim_matches(['"', '\''])));
         }
     }
+    Some((attrs, rest[end..].strip_prefix('}')?))
 }
 
-fn uncons<'a>(chs: &'a str) -> Option<(char, &'a str)> {
-    if let Some(c) = chs.chars().nth(0) {
+fn attribute(attrs: &Attributes, key: &str) -> Option<String> {
+    attrs.iter().find(|&&(k, _)| k == key).map(|&(_, value)| value.to_string())
+}
+
+// split `[ ] ` or `[x] ` at the start of a list item into whether it is checked and the rest
+fn split_task_marker(text: &str) -> (Option<bool>, &str) {
+    let checked = match text.get(..3) {
+        Some("[ ]") => false,
+        Some("[x]" | "[X]") => true,
+        _ => return (None, text),
+    };
+    match text[3..].strip_prefix([' ', '\t']) {
+        Some(rest) if !is_blank(rest) => (Some(checked), rest),
+        _ => (None, text),
+    }
+}
+
+// a scheme followed by `:` and characters other than whitespace, `<` and `>`
+fn is_absolute_uri(text: &str) -> bool {
+    let Some((scheme, _)) = text.split_once(':') else { return false };
+    (2..=32).contains(&scheme.len())
+        && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+.-".contains(c))
+}
+
+fn is_email(text: &str) -> bool {
+    let Some((local, domain)) = text.split_once('@') else { return false };
+    !local.is_empty() && local.chars().all(|c| c.is_ascii_alphanumeric() || ".!#$%&'*+/=?^_`{|}~-".contains(c))
+        && domain.split('.').all(|label| !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'))
+}
+
+// the length of a URL starting with `http://`, `https://` or `www.`, excluding trailing punctuation
+fn url_len(chs: &str) -> Option<usize> {
+    let prefix = ["https://", "http://", "www."].into_iter().find(|prefix| chs.starts_with(prefix))?;
+    let end = chs.find(|c: char| c.is_whitespace() || c == '<').unwrap_or(chs.len());
+    let mut url = &chs[..end];
+    loop {
+        let trimmed = url.trim_end_matches(['?', '!', '.', ',', ':', '*', '_', '~', '\'', '"']);
+        // a closing parenthesis is a part of the URL only if it has the opening one
+        let trimmed = match trimmed.strip_suffix(')') {
+            Some(rest) if trimmed.matches(')').count() > trimmed.matches('(').count() => rest,
+            _ => trimmed,
+        };
+        if trimmed.len() == url.len() {
+            break;
+        }
+        url = trimmed;
+    }
+    // the domain must follow the prefix
+    if url.len() <= prefix.len() {
+        return None;
+    }
+    let domain = &url[prefix.len()..];
+    if !domain.starts_with(|c: char| c.is_alphanumeric()) {
+        return None;
+    }
+    Some(url.len())
+}
+
+// the tag names which start HTML blocks of the 6th kind
+const BLOCK_TAGS: &[&str] = &[
+    "address", "article", "aside", "base", "basefont", "blockquote", "body", "caption", "center", "col",
+    "colgroup", "dd", "details", "dialog", "dir", "div", "dl", "dt", "fieldset", "figcaption", "figure",
+    "footer", "form", "frame", "frameset", "h1", "h2", "h3", "h4", "h5", "h6", "head", "header", "hr", "html",
+    "iframe", "legend", "li", "link", "main", "menu", "menuitem", "nav", "noframes", "ol", "optgroup", "option",
+    "p", "param", "search", "section", "summary", "table", "tbody", "td", "tfoot", "th", "thead", "title", "tr",
+    "track", "ul",
+];
+
+const OPEN_TAG: &str = r#"<[A-Za-z][A-Za-z0-9-]*(?:\s+[A-Za-z_:][A-Za-z0-9_.:-]*(?:\s*=\s*(?:[^"'=<>`\s]+|'[^']*'|"[^"]*"))?)*\s*/?>"#;
+const CLOSING_TAG: &str = r"</[A-Za-z][A-Za-z0-9-]*\s*>";
+
+static RAW_HTML: LazyLock<Regex> = LazyLock::new(|| {
+    let others = r"<!-->|<!--->|<!--.*?-->|<\?.*?\?>|<![A-Za-z][^>]*>|<!\[CDATA\[.*?\]\]>";
+    Regex::new(&format!("^(?s:{}|{}|{})", OPEN_TAG, CLOSING_TAG, others)).unwrap()
+});
+
+static COMPLETE_TAG: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!("^(?:{}|{})[ \t]*$", OPEN_TAG, CLOSING_TAG)).unwrap()
+});
+
+// split a code span opened by the backtick string at the start into its content and the rest,
+// where it is closed by a backtick string of the same length
+fn split_code_span(chs: &str) -> Option<(&str, &str)> {
+    let len = chs.len() - chs.trim_start_matches('`').len();
+    let rest = &chs[len..];
+    let (i, _) = rest.match_indices(&"`".repeat(len)).find(|&(i, _)| {
+        !rest[..i].ends_with('`') && !rest[i + len..].starts_with('`')
+    })?;
+    Some((&rest[..i], &rest[i + len..]))
+}
+
+// split a tag, a comment, a processing instruction, a declaration or a CDATA section into itself and the rest
+fn split_raw_html(chs: &str) -> Option<(&str, &str)> {
+    let end = RAW_HTML.find(chs)?.end();
+    Some(chs.split_at(end))
+}
+
+// the kind of the HTML block starting at the line, numbered as in CommonMark
+fn html_block_start(line: &str) -> Option<u32> {
+    let line = line.trim_end_matches('\r');
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 {
+        return None;
+    }
+    let line = &line[indent..];
+    let rest = line.strip_prefix('<')?;
+    let (name, after) = split_tag_name(rest);
+    if ["pre", "script", "style", "textarea"].contains(&name.as_str()) && (after.is_empty() || after.starts_with([' ', '\t', '>'])) {
+        return Some(1);
+    }
+    if rest.starts_with("!--") {
+        return Some(2);
+    }
+    if rest.starts_with('?') {
+        return Some(3);
+    }
+    if rest.starts_with("![CDATA[") {
+        return Some(5);
+    }
+    if rest.strip_prefix('!').is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_alphabetic())) {
+        return Some(4);
+    }
+    let (name, after) = split_tag_name(rest.strip_prefix('/').unwrap_or(rest));
+    if BLOCK_TAGS.contains(&name.as_str()) && (after.is_empty() || after.starts_with([' ', '\t', '>']) || after.starts_with("/>")) {
+        return Some(6);
+    }
+    if COMPLETE_TAG.is_match(line) {
+        return Some(7);
+    }
+    None
+}
+
+// split the tag name in lowercase and the rest
+fn split_tag_name(chs: &str) -> (String, &str) {
+    let end = chs.find(|c: char| !c.is_ascii_alphanumeric() && c != '-').unwrap_or(chs.len());
+    (chs[..end].to_ascii_lowercase(), &chs[end..])
+}
+
+fn html_block_end(kind: u32, line: &str) -> bool {
+    let line = line.to_ascii_lowercase();
+    match kind {
+        1 => ["</pre>", "</script>", "</style>", "</textarea>"].iter().any(|end| line.contains(end)),
+        2 => line.contains("-->"),
+        3 => line.contains("?>"),
+        4 => line.contains('>'),
+        5 => line.contains("]]>"),
+        _ => false,
+    }
+}
+
+// split a row at the pipes other than escaped ones and ones in code spans
+fn split_table_row(line: &str) -> Vec<&str> {
+    let line = line.trim();
+    let mut seps = Vec::new();
+    let mut chs = line.char_indices().peekable();
+    while let Some((i, c)) = chs.next() {
+        match c {
+            '\\' => { chs.next(); },
+            '`' => {
+                // skip to the closing backtick string of the same length
+                let end = match split_code_span(&line[i..]) {
+                    Some((_, rest)) => line.len() - rest.len(),
+                    None => i + line[i..].len() - line[i..].trim_start_matches('`').len(),
+                };
+                while chs.next_if(|&(j, _)| j < end).is_some() {}
+            },
+            '|' => { seps.push(i); },
+            _ => {},
+        }
+    }
+
+    // leading and trailing pipes are optional
+    let mut start = 0;
+    let mut end = line.len();
+    if seps.first() == Some(&0) {
+        seps.remove(0);
+        start = 1;
+    }
+    if seps.last().is_some_and(|&sep| sep + 1 == line.len()) {
+        seps.pop();
+        end = line.len() - 1;
+    }
+    if start >= end {
+        return Vec::new();
+    }
+
+    let mut cells = Vec::new();
+    let mut begin = start;
+    for sep in seps {
+        cells.push(line[begin..sep].trim());
+        begin = sep + 1;
+    }
+    cells.push(line[begin..end].trim());
+    cells
+}
+
+// `\|` in a table cell stands for `|` even in code spans
+fn unescape_pipes(spans: &mut [Span]) {
+    for span in spans {
+        match span {
+            Code { code } => { *code = code.replace("\\|", "|"); },
+            Link { text: spans, .. } | Emphasis { spans } | Strong { spans } | Strikethrough { spans } => { unescape_pipes(spans); },
+            _ => {},
+        }
+    }
+}
+
+// the alignment of the columns if the row is a delimiter row like `| :-- | :-: | --: |`
+fn table_alignment(row: &[&str]) -> Option<Vec<Option<Align>>> {
+    if row.is_empty() {
+        return None;
+    }
+    row.iter().map(|cell| {
+        if cell.is_empty() {
+            return Some(None);
+        }
+        let left = cell.starts_with(':');
+        let right = cell.ends_with(':');
+        let dashes = cell.trim_start_matches(':').trim_end_matches(':');
+        if dashes.is_empty() || !dashes.chars().all(|c| c == '-') {
+            return None;
+        }
+        Some(match (left, right) {
+            (true, true) => Some(Align::Center),
+            (true, false) => Some(Align::Left),
+            (false, true) => Some(Align::Right),
+            (false, false) => None,
+        })
+    }).collect()
+}
+
+// whether the line starts a paragraph, possibly in a blockquote or a list item
+fn starts_paragraph(mut line: &str) -> bool {
+    loop {
+        if is_blank(line) || is_indented_code(line) {
+            return false;
+        }
+        if let Some(rest) = line.trim_start_matches(' ').strip_prefix('>') {
+            line = rest.strip_prefix(' ').unwrap_or(rest);
+            continue;
+        }
+        let parser = Parser::new(line);
+        if let Some((_, _, width)) = parser.list_marker() {
+            line = line.get(width..).unwrap_or("");
+            continue;
+        }
+        return !parser.interrupts_paragraph() && html_block_start(line).is_none();
+    }
+}
+
+// three or more `-`, `*` or `_`, optionally separated by spaces
+fn is_thematic_break(line: &str) -> bool {
+    let line = line.trim_end();
+    let chs = line.trim_start_matches(' ');
+    if line.len() - chs.len() > 3 {
+        return false;
+    }
+    let Some(c) = chs.chars().next().filter(|c| "-*_".contains(*c)) else {
+        return false;
+    };
+    chs.chars().all(|d| d == c || d == ' ' || d == '\t') && chs.matches(c).count() >= 3
+}
+
+fn is_ordered(marker: char) -> bool {
+    marker == '.' || marker == ')'
+}
+
+fn is_blank(line: &str) -> bool {
+    line.trim().is_empty()
+}
+
+fn is_empty_text(span: &Span) -> bool {
+    matches!(span, Text { text } if text.is_empty())
+}
+
+// the beginning and the end of the line count as whitespace
+fn is_whitespace(c: Option<char>) -> bool {
+    c.is_none_or(char::is_whitespace)
+}
+
+fn is_punctuation(c: Option<char>) -> bool {
+    c.is_some_and(|c| c.is_ascii_punctuation() || !(c.is_ascii() || c.is_alphanumeric() || c.is_whitespace()))
+}
+
+// split `[@eq:label]`, `[@fig:label]`, `[@tbl:label]` or `\\eqref{label}` into the label and the rest
+fn split_ref(chs: &str) -> Option<(&str, &str)> {
+    let (rest, close) = if let Some(rest) = chs.strip_prefix("[@") {
+        (rest, ']')
+    } else {
+        (chs.strip_prefix("\\eqref{")?, '}')
+    };
+    let end = rest.find(|c: char| !(c.is_alphanumeric() || ":-_.".contains(c)))?;
+    let label = &rest[..end];
+    let rest = rest[end..].strip_prefix(close)?;
+    if label.is_empty() || close == ']' && rest.starts_with('(') {
+        return None;
+    }
+    Some((label, rest))
+}
+
+// a label without the kind is given the default one, so `\\label{energy}` and `{#eq:energy}` name the same equation
+fn label_of(name: &str, kind: &str) -> String {
+    let name = name.trim();
+    match name.split_once(':') {
+        Some(("eq" | "fig" | "tbl", _)) => name.to_string(),
+        _ => format!("{}:{}", kind, name),
+    }
+}
+
+// the text of a reference, in which the number is `??` if the label is not defined like LaTeX
+pub fn ref_text(label: &str, number: Option<usize>) -> String {
+    let number = number.map_or(String::from("??"), |number| number.to_string());
+    match label.split_once(':') {
+        Some(("fig", _)) => format!("Figure {}", number),
+        Some(("tbl", _)) => format!("Table {}", number),
+        _ => format!("({})", number),
+    }
+}
+
+// give the references the numbers of labels, which may be defined after them
+fn resolve_refs(blocks: &mut [Block], labels: &HashMap<String, usize>) {
+    for block in blocks {
+        match block {
+            Header { spans, .. } | Paragraph { spans } => { resolve_span_refs(spans, labels); },
+            Table { head, body, .. } => {
+                for cell in head.iter_mut().chain(body.iter_mut()).flatten() {
+                    resolve_span_refs(cell, labels);
+                }
+            },
+            Figure { caption, block, .. } => {
+                resolve_span_refs(caption, labels);
+                resolve_refs(std::slice::from_mut(block.as_mut()), labels);
+            },
+            Blockquote { blocks } => { resolve_refs(blocks, labels); },
+            ListElement(list) => {
+                for item in &mut list.items {
+                    resolve_refs(&mut item.blocks, labels);
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
+fn resolve_span_refs(spans: &mut [Span], labels: &HashMap<String, usize>) {
+    for span in spans {
+        match span {
+            Ref { label, number } => { *number = labels.get(label).copied(); },
+            Link { text: spans, .. } | Emphasis { spans } | Strong { spans } | Strikethrough { spans } => { resolve_span_refs(spans, labels); },
+            _ => {},
+        }
+    }
+}
+
+fn plain_text(spans: &Vec<Span>) -> String {
+    let mut text = String::new();
+    for span in spans {
+        match span {
+            Link { text: spans, .. } => { text.push_str(&plain_text(spans)); },
+            Span::Image { alt, .. } => { text.push_str(alt); },
+            Emphasis { spans } => { text.push_str(&plain_text(spans)); },
+            Strong { spans } => { text.push_str(&plain_text(spans)); },
+            Strikethrough { spans } => { text.push_str(&plain_text(spans)); },
+            Math { math } => { text.push_str(&format!("\\({}\\)", math)); },
+            Ref { label, number } => { text.push_str(&ref_text(label, *number)); },
+            Code { code } => { text.push_str(code); },
+            Text { text: t } => { text.push_str(t); },
+            SoftBreak | HardBreak => { text.push(' '); },
+            FootnoteRef { .. } | Span::Html { .. } => {},
+        }
+    }
+    text
+}
+
+fn uncons(chs: &str) -> Option<(char, &str)> {
+    if let Some(c) = chs.chars().next() {
         let i = if let Some((i, _)) = chs.char_indices().nth(1) { i } else { chs.len() };
         return Some((c, &chs[i..]));
     }
@@ -504,7 +1799,7 @@ fn uncons<'a>(chs: &'a str) -> Option<(char, &'a str)> {
 }
 
 fn uncons_except<'a>(chs: &'a str, except: &str) -> Option<(char, &'a str)> {
-    if let Some(c) = chs.chars().nth(0) {
+    if let Some(c) = chs.chars().next() {
         if !except.contains(c) {
             let i = if let Some((i, _)) = chs.char_indices().nth(1) { i } else { chs.len() };
             return Some((c, &chs[i..]));
@@ -513,7 +1808,7 @@ fn uncons_except<'a>(chs: &'a str, except: &str) -> Option<(char, &'a str)> {
     None
 }
 
-fn uncons_except_newline<'a>(chs: &'a str) -> Option<(char, &'a str)> {
+fn uncons_except_newline(chs: &str) -> Option<(char, &str)> {
     uncons_except(chs, "\r\n")
 }
 
@@ -528,9 +1823,9 @@ async fn get_title(url: &String) -> String {
     };
     let regex = Regex::new("<title>(.*)</title>").unwrap();
     if let Some(caps) = regex.captures(&body) {
-        return caps[1].to_string().clone();
+        return decode_entities(&caps[1]);
     }
-    return String::new();
+    String::new()
 }
 
 #[tokio::main]
@@ -551,10 +1846,10 @@ async fn get_ogp_info(url: &String) -> (String, Option<String>, Option<String>,
     let regex = Regex::new("property=\"og:([^\"]*)\" content=\"([^\"]*)\"").unwrap();
     for caps in regex.captures_iter(&body) {
         match &caps[1] {
-            "title" => { title = caps[2].to_string(); },
-            "image" => { image = Some(caps[2].to_string()); },
-            "description" => { description = Some(caps[2].to_string()); },
-            "site_name" => { site_name = Some(caps[2].to_string()); },
+            "title" => { title = decode_entities(&caps[2]); },
+            "image" => { image = Some(decode_entities(&caps[2])); },
+            "description" => { description = Some(decode_entities(&caps[2])); },
+            "site_name" => { site_name = Some(decode_entities(&caps[2])); },
             _ => {},
         }
     }
@@ -562,9 +1857,23 @@ async fn get_ogp_info(url: &String) -> (String, Option<String>, Option<String>,
     if title.is_empty() {
         let regex = Regex::new("<title>(.*)</title>").unwrap();
         if let Some(caps) = regex.captures(&body) {
-            title = caps[1].to_string();
+            title = decode_entities(&caps[1]);
         }
     }
 
     (title, image, description, site_name)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn url_len_without_domain() {
+        assert_eq!(url_len("www. for details"), None);
+        assert_eq!(url_len("www.,"), None);
+        assert_eq!(url_len("www."), None);
+        assert_eq!(url_len("https://"), None);
+        assert_eq!(url_len("www.example.com."), Some(15));
+    }
+}
@@ -0,0 +1,192 @@
+// the safe mode for untrusted documents, which rewrites every block and span before code generation
+
+use std::sync::LazyLock;
+use regex::Regex;
+
+use crate::data::*;
+use crate::entity::decode_entities;
+use crate::codegen::escape_attr;
+use Block::*;
+use Span::*;
+
+const SAFE_SCHEMES: &[&str] = &["http", "https", "mailto", "tel"];
+
+// the tags kept in raw HTML, whose attributes are checked one by one
+const SAFE_TAGS: &[&str] = &[
+    "a", "abbr", "b", "blockquote", "br", "caption", "cite", "code", "dd", "del", "details", "dfn", "div", "dl", "dt",
+    "em", "figcaption", "figure", "h1", "h2", "h3", "h4", "h5", "h6", "hr", "i", "img", "ins", "kbd", "li", "mark",
+    "ol", "p", "pre", "q", "rp", "rt", "ruby", "s", "samp", "small", "span", "strong", "sub", "summary", "sup",
+    "table", "tbody", "td", "tfoot", "th", "thead", "time", "tr", "u", "ul", "var", "wbr",
+];
+
+// the tags removed together with their content
+const DROPPED_TAGS: &[&str] = &["script", "style", "textarea", "title", "noscript", "iframe", "object", "template"];
+
+const URL_ATTRS: &[&str] = &["href", "src", "cite"];
+
+static HTML_TOKEN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?s)<!--.*?-->|<[?!].*?>|<(/?)([A-Za-z][A-Za-z0-9-]*)((?:\s+[A-Za-z_:][A-Za-z0-9_.:-]*(?:\s*=\s*(?:[^"'=<>`\s]+|'[^']*'|"[^"]*"))?)*)\s*/?>"#).unwrap()
+});
+
+static HTML_ATTR: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"([A-Za-z_:][A-Za-z0-9_.:-]*)(?:\s*=\s*(?:"([^"]*)"|'([^']*)'|([^"'=<>`\s]+)))?"#).unwrap()
+});
+
+// commands of MathJax which make links or attributes
+static MATH_COMMAND: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\\(?:href|url|class|cssId|style|data)\s*\{[^}]*\}").unwrap()
+});
+
+pub fn sanitize(content: &mut [Block], footnotes: &mut [Footnote]) {
+    sanitize_blocks(content);
+    for footnote in footnotes {
+        sanitize_blocks(&mut footnote.blocks);
+    }
+}
+
+// a relative URL or one with an allowed scheme, ignoring the characters browsers ignore
+pub fn is_safe_url(url: &str) -> bool {
+    let url: String = decode_entities(url).chars().filter(|c| !c.is_ascii_whitespace() && !c.is_control()).collect();
+    match url.find([':', '/', '?', '#']) {
+        Some(i) if url[i..].starts_with(':') => SAFE_SCHEMES.contains(&url[..i].to_ascii_lowercase().as_str()),
+        _ => true,
+    }
+}
+
+pub fn is_external_url(url: &str) -> bool {
+    let url = url.trim_start().to_ascii_lowercase();
+    url.starts_with("http://") || url.starts_with("https://") || url.starts_with("//")
+}
+
+fn sanitize_blocks(blocks: &mut [Block]) {
+    for block in blocks {
+        sanitize_block(block);
+    }
+}
+
+// every variant is matched without a wildcard so that new ones cannot slip through
+fn sanitize_block(block: &mut Block) {
+    match block {
+        Header { spans, .. } | Paragraph { spans } => { sanitize_spans(spans); },
+        Blockquote { blocks } => { sanitize_blocks(blocks); },
+        ListElement(list) => { sanitize_list(list); },
+        Block::Image { alt, url, .. } => {
+            if !is_safe_url(url) {
+                *block = Paragraph { spans: vec![ Text { text: alt.clone() } ] };
+            }
+        },
+        LinkCard { title, image, url, .. } => {
+            if !is_safe_url(url) {
+                *block = Paragraph { spans: vec![ Text { text: title.clone() } ] };
+            } else if image.as_deref().is_some_and(|image| !is_safe_url(image)) {
+                *image = None;
+            }
+        },
+        MathBlock { math, .. } => { sanitize_math(math); },
+        CodeBlock { .. } | ThematicBreak => {},
+        Table { head, body, .. } => {
+            for cell in head.iter_mut().chain(body.iter_mut()).flatten() {
+                sanitize_spans(cell);
+            }
+        },
+        Figure { caption, block, .. } => {
+            sanitize_spans(caption);
+            sanitize_block(block);
+        },
+        Block::Html { html } => { *html = sanitize_html(html); },
+    }
+}
+
+fn sanitize_list(list: &mut List) {
+    for item in &mut list.items {
+        sanitize_blocks(&mut item.blocks);
+    }
+}
+
+fn sanitize_spans(spans: &mut [Span]) {
+    for span in spans {
+        match span {
+            Link { text, url, .. } => {
+                sanitize_spans(text);
+                if !is_safe_url(url) {
+                    *url = String::from("#");
+                }
+            },
+            Span::Image { alt, url, .. } => {
+                if !is_safe_url(url) {
+                    *span = Text { text: alt.clone() };
+                }
+            },
+            Emphasis { spans } | Strong { spans } | Strikethrough { spans } => { sanitize_spans(spans); },
+            Math { math } => { sanitize_math(math); },
+            Span::Html { html } => { *html = sanitize_html(html); },
+            Ref { .. } | FootnoteRef { .. } | Code { .. } | Text { .. } | SoftBreak | HardBreak => {},
+        }
+    }
+}
+
+fn sanitize_math(math: &mut String) {
+    *math = MATH_COMMAND.replace_all(math, "").into_owned();
+}
+
+// keep only the safe tags and attributes, and escape everything else
+fn sanitize_html(html: &str) -> String {
+    let mut res = String::new();
+    let mut rest = html;
+    while let Some(caps) = HTML_TOKEN.captures(rest) {
+        let token = caps.get(0).unwrap();
+        res.push_str(&rest[..token.start()].replace('<', "&lt;"));
+        rest = &rest[token.end()..];
+
+        // comments, processing instructions and declarations are dropped
+        let Some(name) = caps.get(2) else { continue };
+        let name = name.as_str().to_ascii_lowercase();
+        let closing = !caps[1].is_empty();
+        if DROPPED_TAGS.contains(&name.as_str()) {
+            if !closing {
+                let end = format!("</{}", name);
+                let lower = rest.to_ascii_lowercase();
+                rest = match lower.find(&end) {
+                    Some(i) => rest[i..].split_once('>').map_or("", |(_, rest)| rest),
+                    None => "",
+                };
+            }
+            continue;
+        }
+        if !SAFE_TAGS.contains(&name.as_str()) {
+            continue;
+        }
+        if closing {
+            res.push_str(&format!("</{}>", name));
+        } else {
+            res.push_str(&format!("<{}{}>", name, sanitize_attrs(&name, &caps[3])));
+        }
+    }
+    res.push_str(&rest.replace('<', "&lt;"));
+    res
+}
+
+fn sanitize_attrs(name: &str, attrs: &str) -> String {
+    let mut res = String::new();
+    let mut external = false;
+    for caps in HTML_ATTR.captures_iter(attrs) {
+        let key = caps[1].to_ascii_lowercase();
+        let value = [2, 3, 4].iter().find_map(|&i| caps.get(i)).map_or("", |value| value.as_str());
+        let value = decode_entities(value);
+        // event handlers and styles may run scripts
+        if key.starts_with("on") || key == "style" || key == "srcdoc" || key == "rel" {
+            continue;
+        }
+        if URL_ATTRS.contains(&key.as_str()) {
+            if !is_safe_url(&value) {
+                continue;
+            }
+            external |= key == "href" && is_external_url(&value);
+        }
+        res.push_str(&format!(" {}=\"{}\"", key, escape_attr(&value)));
+    }
+    if name == "a" && external {
+        res.push_str(" rel=\"nofollow noopener\"");
+    }
+    res
+}
@@ -10,20 +10,20 @@ pub fn read_template(path: &str) -> Result<Vec<Elem>, io::Error> {
     let mut reader = BufReader::new(file);
     let mut line = String::new();
     let mut template: Vec<Elem> = Vec::new();
-    let pattern = Regex::new("\\{[a-z]+\\}").unwrap();
+    let pattern = Regex::new("\\{(?:[a-z]+|meta\\.[A-Za-z0-9_.-]+)\\}").unwrap();
 
     while reader.read_line(&mut line)? > 0 {
-        let mut text_iter = pattern.split(&line);
+        let text_iter = pattern.split(&line);
         let mut attr_iter = pattern.find_iter(&line);
-        loop {
-            if let Some(text) = text_iter.next() {
-                template.push(Str(text.to_string()));
-            } else {
-                break;
-            }
+        for text in text_iter {
+            template.push(Str(text.to_string()));
             if let Some(attr) = attr_iter.next() {
                 template.push(match attr.as_str() {
                     "{title}" => Title,
+                    "{author}" => Author,
+                    "{description}" => Description,
+                    "{tags}" => Tags,
+                    "{draft}" => Draft,
                     "{year}" => Year,
                     "{month}" => Month,
                     "{day}" => Day,
@@ -32,6 +32,8 @@ pub fn read_template(path: &str) -> Result<Vec<Elem>, io::Error> {
                     "{second}" => Second,
                     "{toc}" => Toc(attr.start()),
                     "{content}" => Content(attr.start()),
+                    "{footnotes}" => Footnotes(attr.start()),
+                    attr if attr.starts_with("{meta.") => Meta(attr[6..attr.len() - 1].to_string()),
                     _ => { println!("unknown attribute"); panic!(); },
                 });
             }